@@ -0,0 +1,171 @@
+use std::{fs::File, io::Read, path::PathBuf};
+
+use bevy::{asset::io::file::FileAssetReader, prelude::*, sprite::Anchor};
+
+use crate::death::Dead;
+
+/// Movement shorter than this per frame doesn't turn a character around.
+const MIN_TURN_DISTANCE: f32 = 0.01;
+
+/// Sprite sheets hold one row per direction (down, left, right, up), each an idle frame
+/// followed by the walk cycle, so a frame is a quarter of the sheet each way.
+const WALK_FRAMES: usize = 3;
+const SHEET_COLUMNS: usize = 1 + WALK_FRAMES;
+const SHEET_ROWS: usize = 4;
+const FRAME_DURATION: f32 = 0.15;
+
+/// Characters without a sprite sheet bob up and down by this fraction of their height instead.
+const BOB_HEIGHT: f32 = 0.04;
+const BOB_SPEED: f32 = 12.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Down,
+    Left,
+    Right,
+    Up,
+}
+
+impl Direction {
+    pub fn of(vector: Vec2) -> Self {
+        if vector.x.abs() > vector.y.abs() {
+            if vector.x < 0.0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            }
+        } else if vector.y > 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+
+    fn sheet_row(&self) -> usize {
+        match self {
+            Direction::Down => 0,
+            Direction::Left => 1,
+            Direction::Right => 2,
+            Direction::Up => 3,
+        }
+    }
+}
+
+/// The direction a character last moved in, and whether they are moving right now.
+#[derive(Component)]
+pub struct Facing {
+    pub direction: Vec2,
+    pub moving: bool,
+    last_position: Vec2,
+}
+
+impl Facing {
+    pub fn new(position: Vec2) -> Self {
+        Facing {
+            direction: Vec2::NEG_Y,
+            moving: false,
+            last_position: position,
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct WalkAnimation {
+    timer: f32,
+    frame: usize,
+}
+
+/// Path of a character's sprite sheet, relative to the assets folder.
+pub fn sheet_path(name: &str) -> String {
+    format!("textures/characters/{}_sheet.png", name)
+}
+
+/// Where the asset server looks for assets, which doesn't depend on the working directory.
+fn asset_root() -> PathBuf {
+    FileAssetReader::get_base_path().join("assets")
+}
+
+/// Layout of the sprite sheet at `path`, relative to the assets folder unless absolute, or `None`
+/// if there is no such sheet. The frame size comes from the sheet's PNG header so it is known
+/// before the image has loaded.
+pub fn sheet_layout(path: &str) -> Option<TextureAtlasLayout> {
+    let mut header = [0; 24];
+    File::open(asset_root().join(path))
+        .and_then(|mut file| file.read_exact(&mut header))
+        .ok()?;
+    if &header[12..16] != b"IHDR" {
+        println!("Could not read the size of {}: not a PNG", path);
+        return None;
+    }
+    let width = u32::from_be_bytes(header[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(header[20..24].try_into().unwrap());
+    let frame = Vec2::new(
+        (width as usize / SHEET_COLUMNS) as f32,
+        (height as usize / SHEET_ROWS) as f32,
+    );
+    Some(TextureAtlasLayout::from_grid(
+        frame,
+        SHEET_COLUMNS,
+        SHEET_ROWS,
+        None,
+        None,
+    ))
+}
+
+pub fn update_facing(mut characters: Query<(&mut Facing, &Transform)>) {
+    for (mut facing, transform) in &mut characters {
+        let position = transform.translation.xy();
+        let moved = position - facing.last_position;
+        facing.moving = moved.length() > MIN_TURN_DISTANCE;
+        if facing.moving {
+            facing.direction = moved.normalize();
+        }
+        facing.last_position = position;
+    }
+}
+
+/// Plays the walk cycle for the direction each character faces, or flips and bobs characters
+/// that only have a single sprite.
+pub fn animate_characters(
+    time: Res<Time>,
+    mut characters: Query<
+        (
+            &Facing,
+            &mut WalkAnimation,
+            &mut Sprite,
+            Option<&mut TextureAtlas>,
+        ),
+        Without<Dead>,
+    >,
+) {
+    for (facing, mut animation, mut sprite, atlas) in &mut characters {
+        if facing.moving {
+            animation.timer += time.delta_seconds();
+            animation.frame = (animation.timer / FRAME_DURATION) as usize % WALK_FRAMES;
+        } else {
+            animation.timer = 0.0;
+            animation.frame = 0;
+        }
+
+        let direction = Direction::of(facing.direction);
+        match atlas {
+            Some(mut atlas) => {
+                let column = if facing.moving {
+                    1 + animation.frame
+                } else {
+                    0
+                };
+                atlas.index = direction.sheet_row() * SHEET_COLUMNS + column;
+            }
+            None => {
+                match direction {
+                    Direction::Left => sprite.flip_x = true,
+                    Direction::Right => sprite.flip_x = false,
+                    Direction::Up | Direction::Down => {}
+                }
+                let bob = (animation.timer * BOB_SPEED).sin().abs() * BOB_HEIGHT;
+                sprite.anchor = Anchor::Custom(Vec2::new(0.0, -bob));
+            }
+        }
+    }
+}
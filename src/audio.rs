@@ -0,0 +1,124 @@
+use bevy::{
+    audio::{PlaybackMode, Volume},
+    prelude::*,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::GameClock, Action, ActionPerformed, GameSet};
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AudioFxSettings {
+    pub music_volume: f32,
+    /// Loudest the evening birdsong gets, around dusk.
+    pub ambience_volume: f32,
+    /// Volume of eating, harvesting and voices.
+    pub effects_volume: f32,
+}
+
+impl Default for AudioFxSettings {
+    fn default() -> Self {
+        AudioFxSettings {
+            music_volume: 0.7,
+            ambience_volume: 0.7,
+            effects_volume: 2.0,
+        }
+    }
+}
+
+/// Background music, evening ambience and the sounds of characters eating, harvesting and talking.
+pub struct AudioFxPlugin;
+
+impl Plugin for AudioFxPlugin {
+    fn build(&self, app: &mut App) {
+        crate::configure_sets(app);
+        app.init_resource::<AudioFxSettings>()
+            .add_event::<ActionPerformed>()
+            .add_systems(Startup, start_music)
+            .add_systems(
+                Update,
+                (
+                    update_ambience.in_set(GameSet::Simulation),
+                    play_action_sounds.after(GameSet::Actions),
+                ),
+            );
+    }
+}
+
+/// The looping evening birdsong, loudest around dusk.
+#[derive(Component)]
+pub struct EveningAmbience;
+
+/// How loud the birds are at `hour`: they sing through the evening and fall silent in the dead
+/// of night.
+fn ambience_level(hour: f32) -> f32 {
+    let evening = 1.0 - ((hour - 19.0).abs() / 3.0).clamp(0.0, 1.0);
+    0.2 + 0.8 * evening
+}
+
+fn start_music(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<AudioFxSettings>,
+    clock: Res<GameClock>,
+) {
+    commands.spawn(AudioBundle {
+        source: asset_server.load("sounds/Ethereal_Quest.mp3"),
+        settings: PlaybackSettings {
+            volume: Volume::new(settings.music_volume),
+            mode: PlaybackMode::Loop,
+            ..Default::default()
+        },
+    });
+
+    commands.spawn((
+        EveningAmbience,
+        AudioBundle {
+            source: asset_server.load("sounds/evening-birds.mp3"),
+            settings: PlaybackSettings {
+                volume: Volume::new(settings.ambience_volume * ambience_level(clock.now.hour())),
+                mode: PlaybackMode::Loop,
+                ..Default::default()
+            },
+        },
+    ));
+}
+
+fn update_ambience(
+    clock: Res<GameClock>,
+    settings: Res<AudioFxSettings>,
+    ambience: Query<&AudioSink, With<EveningAmbience>>,
+) {
+    for sink in &ambience {
+        sink.set_volume(settings.ambience_volume * ambience_level(clock.now.hour()));
+    }
+}
+
+fn play_action_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<AudioFxSettings>,
+    mut performed: EventReader<ActionPerformed>,
+) {
+    for event in performed.read() {
+        let sound = match &event.action {
+            Action::Eat => "sounds/eat.mp3".to_string(),
+            Action::Harvest(_) => "sounds/harvest.mp3".to_string(),
+            Action::Talk(_) => format!("sounds/voice{}.mp3", rand::thread_rng().gen_range(1..=6)),
+            _ => continue,
+        };
+        let Some(mut source) = commands.get_entity(event.target.unwrap_or(event.actor)) else {
+            continue;
+        };
+        source.insert(AudioBundle {
+            source: asset_server.load(sound),
+            settings: PlaybackSettings {
+                volume: Volume::new(settings.effects_volume),
+                mode: PlaybackMode::Remove,
+                spatial: true,
+                ..Default::default()
+            },
+        });
+    }
+}
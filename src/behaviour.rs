@@ -0,0 +1,509 @@
+//! Behaviour trees authored in `assets/behaviours.json` that carry out NPC tasks, one tree per
+//! task, built from a few composite nodes and reusable leaves like `move_to`, `harvest` or `flee`.
+//! A tree that succeeds leaves the NPC idle, one that fails says why and does the same.
+
+use std::{collections::HashMap, fs, mem};
+
+use bevy::prelude::*;
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::{
+    animation::Facing,
+    death::Dead,
+    dialog::BUILT_IN_TASKS,
+    navigation::{self, NavigationGrid},
+    needs, Action, Character, CharacterSettings, NPCState, Plant, Region, NPC,
+};
+
+pub const BEHAVIOURS_PATH: &str = "assets/behaviours.json";
+
+/// Distance at which a character counts as having reached another one.
+const PERSON_RANGE: f32 = 60.0;
+
+/// Something to move to, follow or flee from.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    /// The region the NPC is traveling to.
+    Destination,
+    Home,
+    Region(String),
+    /// The closest ripe plant in the region the NPC is in.
+    RipePlant,
+    Person(String),
+}
+
+/// A node of a behaviour tree.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "node", rename_all = "snake_case")]
+pub enum Behaviour {
+    /// Runs the children in order, failing as soon as one fails.
+    Sequence {
+        children: Vec<Behaviour>,
+    },
+    /// Tries the children in order until one doesn't fail.
+    Selector {
+        children: Vec<Behaviour>,
+    },
+    /// Runs the child again each time it succeeds, until it fails.
+    Repeat {
+        child: Box<Behaviour>,
+    },
+    /// Succeeds when the child fails and the other way around.
+    Invert {
+        child: Box<Behaviour>,
+    },
+    /// Succeeds if the target can be found.
+    Exists {
+        target: Target,
+    },
+    /// Walks into the target region, or to within `within` of any other target.
+    MoveTo {
+        target: Target,
+        #[serde(default)]
+        within: Option<f32>,
+    },
+    /// Turns to the closest ripe plant within reach and picks it.
+    Harvest,
+    /// Eats some of the food the NPC carries.
+    Eat,
+    Talk {
+        text: String,
+    },
+    Wait {
+        seconds: f32,
+    },
+    /// Keeps within `distance` of the target for as long as the task lasts.
+    Follow {
+        target: Target,
+        distance: f32,
+    },
+    /// Runs until the target is at least `distance` away, succeeding straight away if there is
+    /// nothing to flee from.
+    Flee {
+        target: Target,
+        distance: f32,
+    },
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Status {
+    Running,
+    Success,
+    /// Why the node failed, e.g. "can't find a way to Bill's Farm".
+    Failure(String),
+}
+
+/// Where a tree is for one NPC: the child each composite node is on and how long each `wait`
+/// has run, keyed by the node's position in the tree.
+#[derive(Default, Debug)]
+pub struct Progress {
+    /// The task the tree runs for, progress is thrown away when it changes.
+    state: Option<NPCState>,
+    cursors: HashMap<usize, usize>,
+    timers: HashMap<usize, f32>,
+}
+
+impl Progress {
+    /// Forgets the progress of the nodes with ids in `ids`.
+    fn reset(&mut self, ids: std::ops::Range<usize>) {
+        self.cursors.retain(|id, _| !ids.contains(id));
+        self.timers.retain(|id, _| !ids.contains(id));
+    }
+}
+
+/// The trees for each task, keyed by the task's name, e.g. `farming`.
+#[derive(Resource, Default)]
+pub struct Behaviours {
+    pub trees: HashMap<String, Behaviour>,
+}
+
+impl Behaviours {
+    /// Loads the trees authored in `assets/behaviours.json`, or the ones the game ships with.
+    pub fn load() -> Self {
+        let trees = fs::read_to_string(BEHAVIOURS_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|source| Behaviours::from_json(&source));
+        trees.unwrap_or_else(|e| {
+            println!("Could not load {}: {}", BEHAVIOURS_PATH, e);
+            Behaviours::from_json(include_str!("../assets/behaviours.json")).unwrap()
+        })
+    }
+
+    /// The tasks the model may pick: the built-in ones and every task with a tree, except sleep,
+    /// which is up to the schedules.
+    pub fn task_names(&self) -> Vec<String> {
+        BUILT_IN_TASKS
+            .iter()
+            .map(|task| task.to_string())
+            .chain(self.trees.keys().cloned())
+            .filter(|task| task != "sleeping")
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        let trees = serde_json::from_str(source).map_err(|e| e.to_string())?;
+        Ok(Behaviours { trees })
+    }
+}
+
+/// Whoever is running a tree.
+struct Agent<'a> {
+    npc: &'a mut NPC,
+    character: &'a mut Character,
+    position: &'a mut Vec3,
+    facing: &'a mut Facing,
+}
+
+/// What the leaves can see and change besides the agent.
+struct Surroundings<'a> {
+    regions: &'a [&'a Region],
+    ripe_plants: &'a [(Entity, Vec2)],
+    /// Living characters by name.
+    people: &'a [(String, Vec2)],
+    /// Where everyone is, to steer around them.
+    others: &'a [Vec2],
+    grid: &'a mut NavigationGrid,
+    /// How far a character walks this frame.
+    stride: f32,
+    delta: f32,
+}
+
+enum Place {
+    Area(Rect),
+    Spot { position: Vec2, range: f32 },
+}
+
+impl Place {
+    fn point(&self) -> Vec2 {
+        match self {
+            Place::Area(range) => range.center(),
+            Place::Spot { position, .. } => *position,
+        }
+    }
+}
+
+impl Target {
+    fn describe(&self, npc: &NPC) -> String {
+        match self {
+            Target::Destination => match &npc.state {
+                NPCState::Traveling(destination) => destination.clone(),
+                _ => "nowhere".to_string(),
+            },
+            Target::Home => "home".to_string(),
+            Target::Region(region) => region.clone(),
+            Target::RipePlant => "any ripe plants".to_string(),
+            Target::Person(person) => person.clone(),
+        }
+    }
+
+    fn locate(&self, agent: &Agent, world: &Surroundings) -> Result<Place, String> {
+        let position = agent.position.xy();
+        let region = |name: &str| {
+            world
+                .regions
+                .iter()
+                .find(|region| region.name == name)
+                .map(|region| Place::Area(region.range))
+                .ok_or_else(|| format!("can't travel to unknown place {}", name))
+        };
+        match self {
+            Target::Destination => match &agent.npc.state {
+                NPCState::Traveling(destination) => region(destination),
+                _ => Err("has nowhere to travel to".to_string()),
+            },
+            Target::Home if agent.npc.home.is_empty() => Err("has no home".to_string()),
+            Target::Home => region(&agent.npc.home),
+            Target::Region(name) => region(name),
+            Target::RipePlant => world
+                .ripe_plants
+                .iter()
+                .map(|(_, plant)| plant)
+                .filter(|plant| {
+                    world.regions.iter().any(|region| {
+                        region.range.contains(position) && region.range.contains(**plant)
+                    })
+                })
+                .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)))
+                .map(|plant| Place::Spot {
+                    position: *plant,
+                    range: Plant::HARVEST_RANGE / 2.0,
+                })
+                .ok_or_else(|| "sees no ripe plants".to_string()),
+            Target::Person(person) => world
+                .people
+                .iter()
+                .find(|(name, _)| name == person && *name != agent.character.name)
+                .map(|(_, position)| Place::Spot {
+                    position: *position,
+                    range: PERSON_RANGE,
+                })
+                .ok_or_else(|| format!("can't find {}", person)),
+        }
+    }
+}
+
+/// Takes one walking step toward `point`, failing once the path ends short of it.
+fn step(agent: &mut Agent, world: &mut Surroundings, point: Vec2, target: &str) -> Status {
+    let step = agent.npc.path.step_towards(
+        world.grid,
+        agent.position,
+        point,
+        world.stride,
+        world.others,
+    );
+    match step {
+        navigation::Step::Unreachable => Status::Failure(format!("can't find a way to {}", target)),
+        // the path ends at the nearest walkable spot, which may not be close enough
+        navigation::Step::Arrived => Status::Failure(format!("can't get any closer to {}", target)),
+        navigation::Step::Moving => Status::Running,
+    }
+}
+
+impl Behaviour {
+    /// Number of nodes in the tree, which decides the ids of the nodes after it.
+    fn size(&self) -> usize {
+        match self {
+            Behaviour::Sequence { children } | Behaviour::Selector { children } => {
+                1 + children.iter().map(Behaviour::size).sum::<usize>()
+            }
+            Behaviour::Repeat { child } | Behaviour::Invert { child } => 1 + child.size(),
+            _ => 1,
+        }
+    }
+
+    /// Runs the node with id `id` for one frame.
+    fn tick(
+        &self,
+        id: usize,
+        agent: &mut Agent,
+        world: &mut Surroundings,
+        progress: &mut Progress,
+    ) -> Status {
+        match self {
+            Behaviour::Sequence { children } | Behaviour::Selector { children } => {
+                let is_sequence = matches!(self, Behaviour::Sequence { .. });
+                let first_child_ids = children.iter().scan(id + 1, |next, child| {
+                    let child_id = *next;
+                    *next += child.size();
+                    Some(child_id)
+                });
+                let child_ids = first_child_ids.collect::<Vec<_>>();
+                let mut cursor = progress.cursors.get(&id).copied().unwrap_or(0);
+                let mut failure = String::new();
+                // instant children run back to back in the same frame
+                while let Some(child) = children.get(cursor) {
+                    match child.tick(child_ids[cursor], agent, world, progress) {
+                        Status::Running => {
+                            progress.cursors.insert(id, cursor);
+                            return Status::Running;
+                        }
+                        Status::Success if !is_sequence => break,
+                        Status::Failure(reason) if is_sequence => {
+                            progress.reset(id..id + self.size());
+                            return Status::Failure(reason);
+                        }
+                        Status::Success => {}
+                        Status::Failure(reason) => failure = reason,
+                    }
+                    cursor += 1;
+                }
+                progress.reset(id..id + self.size());
+                if is_sequence || cursor < children.len() {
+                    Status::Success
+                } else {
+                    Status::Failure(failure)
+                }
+            }
+            Behaviour::Repeat { child } => match child.tick(id + 1, agent, world, progress) {
+                // go again next frame rather than looping forever on instant children
+                Status::Success => {
+                    progress.reset(id..id + self.size());
+                    Status::Running
+                }
+                status => status,
+            },
+            Behaviour::Invert { child } => match child.tick(id + 1, agent, world, progress) {
+                Status::Running => Status::Running,
+                Status::Success => Status::Failure("did the opposite".to_string()),
+                Status::Failure(_) => Status::Success,
+            },
+            Behaviour::Exists { target } => match target.locate(agent, world) {
+                Ok(_) => Status::Success,
+                Err(reason) => Status::Failure(reason),
+            },
+            Behaviour::MoveTo { target, within } => {
+                let place = match target.locate(agent, world) {
+                    Ok(place) => place,
+                    Err(reason) => return Status::Failure(reason),
+                };
+                let position = agent.position.xy();
+                let arrived = match place {
+                    Place::Area(range) => range.contains(position),
+                    Place::Spot {
+                        position: spot,
+                        range,
+                    } => spot.distance(position) <= within.unwrap_or(range),
+                };
+                if arrived {
+                    Status::Success
+                } else {
+                    let name = target.describe(agent.npc);
+                    step(agent, world, place.point(), &name)
+                }
+            }
+            Behaviour::Harvest => {
+                let position = agent.position.xy();
+                let closest = world
+                    .ripe_plants
+                    .iter()
+                    .filter(|(_, plant)| plant.distance(position) < Plant::HARVEST_RANGE)
+                    .min_by(|(_, a), (_, b)| a.distance(position).total_cmp(&b.distance(position)));
+                match closest {
+                    Some(&(entity, plant)) => {
+                        if let Some(direction) = (plant - position).try_normalize() {
+                            agent.facing.direction = direction;
+                        }
+                        agent.character.actions.push(Action::Harvest(Some(entity)));
+                        Status::Success
+                    }
+                    None => Status::Failure("has no ripe plants in reach".to_string()),
+                }
+            }
+            Behaviour::Eat => {
+                if needs::food_count(agent.character) > 0 {
+                    agent.character.actions.push(Action::Eat);
+                    Status::Success
+                } else {
+                    Status::Failure("has nothing to eat".to_string())
+                }
+            }
+            Behaviour::Talk { text } => {
+                agent.character.actions.push(Action::Talk(text.clone()));
+                Status::Success
+            }
+            Behaviour::Wait { seconds } => {
+                let waited = progress.timers.entry(id).or_default();
+                *waited += world.delta;
+                if *waited >= *seconds {
+                    progress.timers.remove(&id);
+                    Status::Success
+                } else {
+                    Status::Running
+                }
+            }
+            Behaviour::Follow { target, distance } => {
+                let place = match target.locate(agent, world) {
+                    Ok(place) => place,
+                    Err(reason) => return Status::Failure(reason),
+                };
+                if place.point().distance(agent.position.xy()) > *distance {
+                    let name = target.describe(agent.npc);
+                    step(agent, world, place.point(), &name)
+                } else {
+                    Status::Running
+                }
+            }
+            Behaviour::Flee { target, distance } => {
+                let Ok(place) = target.locate(agent, world) else {
+                    return Status::Success;
+                };
+                let position = agent.position.xy();
+                let away = position - place.point();
+                if away.length() >= *distance {
+                    return Status::Success;
+                }
+                let direction = away.try_normalize().unwrap_or(Vec2::X);
+                match step(agent, world, position + direction * *distance, "safety") {
+                    Status::Failure(_) => Status::Failure("is cornered".to_string()),
+                    status => status,
+                }
+            }
+        }
+    }
+}
+
+/// Runs the tree of each NPC's task for one frame. NPCs doing a task without a tree, like idling,
+/// are left alone, except for tasks nobody knows how to do, which are given up on.
+#[allow(clippy::too_many_arguments)]
+pub fn run_behaviours(
+    behaviours: Res<Behaviours>,
+    mut npcs: Query<
+        (&mut NPC, &mut Character, &mut Transform, &mut Facing),
+        (Without<Plant>, Without<Dead>),
+    >,
+    // the player and anyone else who isn't an NPC
+    characters: Query<(&Character, &Transform), (Without<NPC>, Without<Dead>)>,
+    plants: Query<(Entity, &Transform, &Plant), Without<Character>>,
+    regions: Query<&Region>,
+    mut grid: ResMut<NavigationGrid>,
+    settings: Res<CharacterSettings>,
+    time: Res<Time>,
+) {
+    let regions = regions.iter().collect::<Vec<_>>();
+    let ripe_plants = plants
+        .iter()
+        .filter(|(_, _, plant)| plant.is_grown())
+        .map(|(entity, transform, _)| (entity, transform.translation.xy()))
+        .collect::<Vec<_>>();
+    let people = npcs
+        .iter()
+        .map(|(_, character, transform, _)| (character, transform))
+        .chain(&characters)
+        .map(|(character, transform)| (character.name.clone(), transform.translation.xy()))
+        .collect::<Vec<_>>();
+    let others = people
+        .iter()
+        .map(|(_, position)| *position)
+        .collect::<Vec<_>>();
+    let mut world = Surroundings {
+        regions: &regions,
+        ripe_plants: &ripe_plants,
+        people: &people,
+        others: &others,
+        grid: &mut grid,
+        stride: settings.speed * time.delta_seconds(),
+        delta: time.delta_seconds(),
+    };
+
+    for (mut npc, mut character, mut transform, mut facing) in &mut npcs {
+        let Some(tree) = behaviours.trees.get(npc.state.name()) else {
+            if matches!(npc.state, NPCState::Activity(_)) {
+                println!(
+                    "{} doesn't know how to {}",
+                    character.name,
+                    npc.state.name()
+                );
+                npc.state = NPCState::Idle;
+            }
+            npc.behaviour = Progress::default();
+            continue;
+        };
+        let mut progress = mem::take(&mut npc.behaviour);
+        if progress.state.as_ref() != Some(&npc.state) {
+            progress = Progress {
+                state: Some(npc.state.clone()),
+                ..default()
+            };
+        }
+        let mut agent = Agent {
+            npc: &mut npc,
+            character: &mut character,
+            position: &mut transform.translation,
+            facing: &mut facing,
+        };
+        let status = tree.tick(0, &mut agent, &mut world, &mut progress);
+        if let Status::Failure(reason) = &status {
+            println!("{} {}", character.name, reason);
+        }
+        if status == Status::Running {
+            npc.behaviour = progress;
+        } else {
+            npc.state = NPCState::Idle;
+            npc.behaviour = Progress::default();
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Serves a fake OpenAI chat completions endpoint, e.g.
+//! `cargo run --bin mock_openai -- --mode rules --script assets/mock_openai/village.json`
+//! and then `OPENAI_ENDPOINT=http://127.0.0.1:8787/v1/chat/completions cargo run`.
+
+use std::{env, net::TcpListener, process, str::FromStr, time::Duration};
+
+use bevy_rpg::mock_server::{self, MockConfig, MockMode, MockScript};
+
+const USAGE: &str =
+    "Usage: mock_openai [--port 8787] [--mode scripted|random|rules] [--script FILE] \
+[--seed N] [--delay-ms N] [--error-rate 0.1] [--timeout-rate 0.1] [--api-key KEY]";
+
+fn main() {
+    let mut port: u16 = 8787;
+    let mut config = MockConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let Some(value) = args.next() else {
+            exit_with_usage(&format!("{} needs a value", flag));
+        };
+        match flag.as_str() {
+            "--port" => port = parse(&flag, &value),
+            "--mode" => {
+                config.mode = MockMode::parse(&value)
+                    .unwrap_or_else(|| exit_with_usage(&format!("Unknown mode {}", value)))
+            }
+            "--script" => {
+                config.script = MockScript::load(&value).unwrap_or_else(|e| {
+                    exit_with_usage(&format!("Could not load {}: {}", value, e))
+                })
+            }
+            "--seed" => config.seed = parse(&flag, &value),
+            "--delay-ms" => config.delay = Duration::from_millis(parse(&flag, &value)),
+            "--error-rate" => config.error_rate = parse(&flag, &value),
+            "--timeout-rate" => config.timeout_rate = parse(&flag, &value),
+            "--api-key" => config.api_key = Some(value),
+            _ => exit_with_usage(&format!("Unknown option {}", flag)),
+        }
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+        println!("Could not listen on port {}: {}", port, e);
+        process::exit(1);
+    });
+    println!(
+        "Mock OpenAI server ({:?} mode) listening on http://127.0.0.1:{}/v1/chat/completions",
+        config.mode, port
+    );
+    mock_server::serve(listener, config);
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    println!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| exit_with_usage(&format!("Invalid value for {}: {}", flag, value)))
+}
@@ -0,0 +1,45 @@
+//! Encrypts an OpenAI API key into the file the game unlocks with `BEVY_RPG_KEY_PASSPHRASE`.
+//! Reads the key and then the passphrase from standard input, one per line.
+
+use std::{
+    env, fs,
+    io::{self, BufRead},
+    path::PathBuf,
+    process,
+};
+
+use bevy_rpg::credentials;
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .or_else(credentials::encrypted_key_path)
+        .unwrap_or_else(|| exit("No config directory, pass the file to write"));
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    println!("API key:");
+    let key = lines.next().and_then(Result::ok).unwrap_or_default();
+    println!("Passphrase:");
+    let passphrase = lines.next().and_then(Result::ok).unwrap_or_default();
+    if key.trim().is_empty() || passphrase.is_empty() {
+        exit("Both the key and a passphrase are needed");
+    }
+
+    let contents = credentials::encrypt_key(&key, &passphrase);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).unwrap_or_else(|e| exit(&e.to_string()));
+    }
+    fs::write(&path, contents).unwrap_or_else(|e| exit(&e.to_string()));
+    println!(
+        "Wrote {}, set {} to unlock it",
+        path.display(),
+        credentials::PASSPHRASE_VAR
+    );
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
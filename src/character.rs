@@ -0,0 +1,490 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Formatter},
+};
+
+use bevy::{prelude::*, text::Text2dBounds};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation, clock,
+    controls::{self, ActionState},
+    death, interaction, navigation, needs, perception, save, GameSet, Plant,
+};
+
+const CHARACTER_SCALE: Vec3 = Vec3::new(0.2, 0.2, 0.0);
+
+/// Tuning for how every character, player and NPC alike, moves and gets hungry.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CharacterSettings {
+    /// Walking speed in world units per second.
+    pub speed: f32,
+    /// Saturation lost per second.
+    pub hunger_per_second: f32,
+}
+
+impl Default for CharacterSettings {
+    fn default() -> Self {
+        CharacterSettings {
+            speed: 150.0,
+            hunger_per_second: 0.2,
+        }
+    }
+}
+
+/// Characters and what they do: player controls, hunger, inventories, interaction, death and
+/// saving.
+pub struct CharacterPlugin;
+
+impl Plugin for CharacterPlugin {
+    fn build(&self, app: &mut App) {
+        crate::configure_sets(app);
+        crate::insert_if_missing(app, controls::Controls::load);
+        app.init_resource::<CharacterSettings>()
+            .init_resource::<ActionState>()
+            .init_resource::<controls::ControlsMenu>()
+            .init_resource::<interaction::InteractionTarget>()
+            .init_resource::<death::DeathNews>()
+            .add_event::<ActionPerformed>()
+            .add_event::<death::CharacterDied>()
+            .add_event::<save::SaveGame>()
+            .add_event::<save::LoadGame>()
+            .add_systems(
+                PreUpdate,
+                controls::read_actions.after(bevy::input::InputSystem),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        player_input,
+                        camera_follow_player,
+                        inventory_update,
+                        update_saturation,
+                        needs::show_hunger,
+                    )
+                        .in_set(GameSet::Simulation),
+                    (interaction::find_interaction_target, interaction::interact)
+                        .chain()
+                        .in_set(GameSet::Interaction),
+                    animation::update_facing.in_set(GameSet::Perception),
+                    animation::animate_characters
+                        .after(animation::update_facing)
+                        .in_set(GameSet::Perception),
+                    handle_actions.in_set(GameSet::Actions),
+                    (death::lay_down_corpses, death::spread_death_news).after(update_saturation),
+                ),
+            )
+            .add_systems(PostUpdate, (save::save_game, save::load_game).chain());
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Clone, Serialize, Deserialize)]
+pub enum Item {
+    Plant,
+    #[allow(dead_code)]
+    Meat,
+}
+
+impl Item {
+    pub fn saturation(&self) -> f32 {
+        match self {
+            Item::Plant => 10.0,
+            Item::Meat => 20.0,
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Item::Plant => write!(f, "Plant"),
+            Item::Meat => write!(f, "Meat"),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub enum Action {
+    Eat,
+    /// Picks a ripe plant: the one given, or else the one most directly in front of the actor.
+    /// The plant isn't remembered, entities don't outlive a save.
+    Harvest(#[serde(skip)] Option<Entity>),
+    Talk(String),
+    Emote(String),
+    /// Hands one piece of food to the named character.
+    Give(String),
+    /// Something that happened in the village, only ever remembered rather than performed.
+    Event(String),
+}
+
+impl Action {
+    pub fn get_context(&self, actor: &str) -> String {
+        match self {
+            Action::Eat => format!("{} eats something. ", actor),
+            Action::Harvest(_) => format!("{} harvests. ", actor),
+            Action::Talk(speech) => format!("{} says \"{}\". ", actor, speech),
+            Action::Emote(emote) => format!("{} {}. ", actor, emote),
+            Action::Give(recipient) => format!("{} gives food to {}. ", actor, recipient),
+            Action::Event(description) => format!("{} ", description),
+        }
+    }
+}
+
+/// Something an NPC saw or heard, and when.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Memory {
+    pub time: clock::GameTime,
+    pub actor: String,
+    pub action: Action,
+}
+
+impl Memory {
+    pub fn get_context(&self) -> String {
+        format!(
+            "[{}] {}",
+            self.time.timestamp(),
+            self.action.get_context(&self.actor).trim()
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct Character {
+    pub name: String,
+    pub items: Vec<(Item, u32)>,
+    pub saturation: f32,
+    pub actions: Vec<Action>,
+}
+
+impl Default for Character {
+    fn default() -> Self {
+        Character {
+            name: "".to_string(),
+            items: vec![],
+            saturation: 100.0,
+            actions: vec![],
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct Player {
+    pub text_box: String,
+}
+
+#[derive(Component, Deref, DerefMut)]
+pub struct StartPos(pub Vec2);
+
+/// An action a character actually carried out this frame, e.g. a harvest that found a ripe plant.
+#[derive(Event)]
+pub struct ActionPerformed {
+    pub actor: Entity,
+    pub action: Action,
+    /// What the action was done to, such as the harvested plant.
+    pub target: Option<Entity>,
+}
+
+/// Gives a newly spawned character their sprite, speech bubble and senses. Use it with
+/// `commands.spawn((StartPos(..), Character { .. })).add(fill_character)`.
+pub fn fill_character(mut entity: EntityWorldMut<'_>) {
+    let start_pos = entity
+        .get::<StartPos>()
+        .unwrap_or(&StartPos(Vec2::new(0.0, 0.0)))
+        .0;
+    let char_name = entity.get::<Character>().unwrap().name.clone();
+    let sheet_path = animation::sheet_path(&char_name);
+    let sheet_layout = animation::sheet_layout(&sheet_path);
+    let texture = entity.world_scope(|world| {
+        let asset_server = world.get_resource::<AssetServer>().unwrap();
+        if sheet_layout.is_some() {
+            asset_server.load(sheet_path)
+        } else {
+            asset_server.load(format!("textures/characters/{}.png", char_name))
+        }
+    });
+    if let Some(sheet_layout) = sheet_layout {
+        let layout = entity.world_scope(|world| {
+            world
+                .resource_mut::<Assets<TextureAtlasLayout>>()
+                .add(sheet_layout)
+        });
+        entity.insert(TextureAtlas { layout, index: 0 });
+    }
+    if !entity.contains::<perception::Perception>() {
+        entity.insert(perception::Perception::default());
+    }
+    entity.insert((
+        animation::Facing::new(start_pos),
+        animation::WalkAnimation::default(),
+    ));
+    entity.insert(SpriteBundle {
+        texture,
+        transform: Transform {
+            translation: start_pos.extend(0.0),
+            scale: CHARACTER_SCALE,
+            ..default()
+        },
+        ..default()
+    });
+    let text_child_id = entity.world_scope(|world| {
+        let asset_server = world.get_resource::<AssetServer>().unwrap();
+
+        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+        let text_style = TextStyle {
+            font: font.clone(),
+            font_size: 12.0,
+            color: Color::BLACK,
+        };
+        let text_alignment = JustifyText::Center;
+        world
+            .spawn((Text2dBundle {
+                text: Text::from_section("", text_style.clone()).with_justify(text_alignment),
+                transform: Transform {
+                    translation: Vec3::new(0.0, -200.0, 10.0),
+                    scale: Vec3::new(5.0, 5.0, 0.0),
+                    ..default()
+                },
+                text_2d_bounds: Text2dBounds {
+                    size: Vec2::new(200.0, 200.0),
+                },
+                text_anchor: bevy::sprite::Anchor::TopCenter,
+                ..default()
+            },))
+            .id()
+    });
+    entity.add_child(text_child_id);
+}
+
+/// Positions of every living character, for NPCs to steer around.
+pub fn character_positions<'a>(transforms: impl Iterator<Item = &'a Transform>) -> Vec<Vec2> {
+    transforms
+        .map(|transform| transform.translation.xy())
+        .collect()
+}
+
+fn player_input(
+    actions: Res<ActionState>,
+    settings: Res<CharacterSettings>,
+    mut query: Query<&mut Transform, (With<Player>, Without<death::Dead>)>,
+    others: Query<&Transform, (With<Character>, Without<Player>, Without<death::Dead>)>,
+    grid: Res<navigation::NavigationGrid>,
+    time: Res<Time>,
+) {
+    let Ok(mut transform) = query.get_single_mut() else {
+        return;
+    };
+    let direction = actions.movement;
+
+    // Calculate the new horizontal paddle position based on player input
+    let new_paddle_position = grid.slide(
+        transform.translation.xy(),
+        direction * settings.speed * time.delta_seconds(),
+        &character_positions(others.iter()),
+    );
+
+    // Update the paddle position,
+
+    transform.translation.x = new_paddle_position.x;
+    transform.translation.y = new_paddle_position.y;
+}
+
+fn camera_follow_player(
+    player_query: Query<&Transform, With<Player>>,
+    mut camera_query: Query<&mut Transform, (With<Camera2d>, Without<Player>)>,
+) {
+    const CAMERA_MAX_DISTANCE: f32 = 200.0;
+    for player_transform in player_query.iter() {
+        for mut camera_transform in &mut camera_query.iter_mut() {
+            if camera_transform
+                .translation
+                .distance(player_transform.translation)
+                > CAMERA_MAX_DISTANCE
+            {
+                camera_transform.translation = player_transform.translation
+                    + (camera_transform.translation - player_transform.translation).normalize()
+                        * CAMERA_MAX_DISTANCE;
+            }
+        }
+    }
+}
+
+// stack items and auto eat if saturation is low
+fn inventory_update(mut query: Query<&mut Character>) {
+    for mut character in &mut query.iter_mut() {
+        let mut new_items = HashMap::new();
+        for (item, count) in character.items.clone() {
+            let new_count = new_items.entry(item).or_insert(0);
+            *new_count += count;
+        }
+        character.items.clear();
+        for (item, count) in new_items {
+            character.items.push((item, count));
+        }
+        // remove empty items
+        character.items.retain(|(_, count)| *count > 0);
+    }
+}
+
+fn update_saturation(
+    mut commands: Commands,
+    settings: Res<CharacterSettings>,
+    mut query: Query<(Entity, &mut Character, &Transform), Without<death::Dead>>,
+    mut deaths: EventWriter<death::CharacterDied>,
+    time: Res<Time>,
+) {
+    for (entity, mut character, transform) in &mut query.iter_mut() {
+        character.saturation -= settings.hunger_per_second * time.delta_seconds();
+        if character.saturation < 0.0 {
+            println!("{} starved to death", character.name);
+            let cause = "starvation".to_string();
+            deaths.send(death::CharacterDied {
+                name: character.name.clone(),
+                position: transform.translation.xy(),
+                cause: cause.clone(),
+            });
+            commands.entity(entity).insert(death::Dead {
+                cause,
+                time_of_death: time.elapsed_seconds(),
+            });
+        } else if character.saturation < needs::HUNGRY_SATURATION
+            && character
+                .items
+                .iter()
+                .any(|(item, _)| item.saturation() > 0.0)
+        {
+            character.actions.push(Action::Eat);
+        }
+    }
+}
+
+/// Carries out every character's queued actions and clears the queue.
+fn handle_actions(
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &animation::Facing,
+        &mut Character,
+        Option<&Children>,
+        Has<death::Dead>,
+    )>,
+    mut plants: Query<(Entity, &Transform, &mut Plant)>,
+    mut text_query: Query<&mut Text>,
+    mut performed: EventWriter<ActionPerformed>,
+) {
+    // gifts only go to someone alive to take them
+    let recipients = query
+        .iter()
+        .filter(|(.., dead)| !dead)
+        .map(|(_, _, _, character, ..)| character.name.clone())
+        .collect::<HashSet<_>>();
+    let mut gifts = vec![];
+    for (character_entity, character_transform, facing, mut character, children, _) in
+        &mut query.iter_mut()
+    {
+        for action in character.actions.clone() {
+            let mut target = None;
+            let done = match &action {
+                Action::Eat => {
+                    let mut eaten = false;
+                    for (item, count) in &mut character.items {
+                        // several meals in one frame can empty a stack before it's tidied up
+                        if item.saturation() > 0.0 && *count > 0 {
+                            *count -= 1;
+                            character.saturation += item.saturation();
+                            eaten = true;
+                            break;
+                        }
+                    }
+                    eaten
+                }
+                Action::Harvest(chosen) => {
+                    let position = character_transform.translation.xy();
+                    let in_reach = |plant_transform: &Transform| {
+                        plant_transform.translation.xy().distance(position) < Plant::HARVEST_RANGE
+                    };
+                    target = match chosen {
+                        Some(chosen) => plants
+                            .get(*chosen)
+                            .ok()
+                            .filter(|(_, plant_transform, plant)| {
+                                plant.is_grown() && in_reach(plant_transform)
+                            })
+                            .map(|(plant_entity, ..)| plant_entity),
+                        // only the ripe plant most directly in front of the character is picked
+                        None => plants
+                            .iter()
+                            .filter(|(_, _, plant)| plant.is_grown())
+                            .filter_map(|(plant_entity, plant_transform, _)| {
+                                interaction::in_front(
+                                    position,
+                                    facing,
+                                    plant_transform.translation.xy(),
+                                    Plant::HARVEST_RANGE,
+                                )
+                                .map(|score| (plant_entity, score))
+                            })
+                            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                            .map(|(plant_entity, _)| plant_entity),
+                    };
+                    if let Some((_, _, mut plant)) =
+                        target.and_then(|target| plants.get_mut(target).ok())
+                    {
+                        character.items.push((Item::Plant, 1));
+                        plant.growth = 0.0;
+                    }
+                    target.is_some()
+                }
+                Action::Give(recipient)
+                    if *recipient == character.name || !recipients.contains(recipient) =>
+                {
+                    false
+                }
+                Action::Give(recipient) => {
+                    if let Some((item, count)) = character
+                        .items
+                        .iter_mut()
+                        .find(|(item, count)| item.saturation() > 0.0 && *count > 0)
+                    {
+                        *count -= 1;
+                        gifts.push((recipient.clone(), item.clone()));
+                        true
+                    } else {
+                        false
+                    }
+                }
+                Action::Event(_) => false,
+                Action::Emote(emote) => {
+                    for &child in children.into_iter().flatten() {
+                        text_query.get_mut(child).unwrap().sections[0].value =
+                            format!("*{}*", emote);
+                    }
+                    true
+                }
+                Action::Talk(speech) => {
+                    for &child in children.into_iter().flatten() {
+                        text_query.get_mut(child).unwrap().sections[0].value = speech.clone();
+                    }
+                    true
+                }
+            };
+            if done {
+                performed.send(ActionPerformed {
+                    actor: character_entity,
+                    action,
+                    target,
+                });
+            }
+        }
+        character.actions.clear();
+    }
+    for (recipient, item) in gifts {
+        if let Some((_, _, _, mut character, ..)) = query
+            .iter_mut()
+            .find(|(_, _, _, character, _, dead)| !dead && character.name == recipient)
+        {
+            character.items.push((item, 1));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Game minutes that pass per real second, making a day last 20 minutes.
+const MINUTES_PER_SECOND: f32 = 1.2;
+pub const MINUTES_PER_DAY: f32 = 24.0 * 60.0;
+const DAYS_PER_SEASON: u32 = 7;
+const START_HOUR: f32 = 8.0;
+
+const SUNRISE_HOUR: f32 = 6.0;
+const SUNSET_HOUR: f32 = 20.0;
+/// Hours it takes the light to fade in or out around sunrise and sunset.
+const TWILIGHT_HOURS: f32 = 1.5;
+const NIGHT_TINT: Color = Color::rgba(0.02, 0.02, 0.12, 0.6);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        }
+    }
+}
+
+/// A point in game time, counted in minutes since the start of day 1.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Default, Debug, Serialize, Deserialize)]
+pub struct GameTime(pub f32);
+
+impl GameTime {
+    /// Days are counted from 1.
+    pub fn day(&self) -> u32 {
+        (self.0 / MINUTES_PER_DAY) as u32 + 1
+    }
+
+    /// Fractional hour of the day, from 0 up to 24.
+    pub fn hour(&self) -> f32 {
+        self.0.rem_euclid(MINUTES_PER_DAY) / 60.0
+    }
+
+    pub fn season(&self) -> Season {
+        match ((self.day() - 1) / DAYS_PER_SEASON) % 4 {
+            0 => Season::Spring,
+            1 => Season::Summer,
+            2 => Season::Autumn,
+            _ => Season::Winter,
+        }
+    }
+
+    /// How dark it is, from 0 in full daylight to 1 in the middle of the night.
+    pub fn darkness(&self) -> f32 {
+        let hour = self.hour();
+        let after_sunset = ((hour - SUNSET_HOUR) / TWILIGHT_HOURS).clamp(0.0, 1.0);
+        let before_sunrise =
+            ((SUNRISE_HOUR + TWILIGHT_HOURS - hour) / TWILIGHT_HOURS).clamp(0.0, 1.0);
+        after_sunset.max(before_sunrise)
+    }
+
+    pub fn part_of_day(&self) -> &'static str {
+        match self.hour() {
+            hour if hour < 5.0 => "night",
+            hour if hour < 12.0 => "morning",
+            hour if hour < 17.0 => "afternoon",
+            hour if hour < 21.0 => "evening",
+            _ => "night",
+        }
+    }
+
+    /// The next time the clock shows `hour`, later today or tomorrow.
+    pub fn next_hour(&self, hour: f32) -> GameTime {
+        let start_of_day = self.0 - self.0.rem_euclid(MINUTES_PER_DAY);
+        let today = GameTime(start_of_day + hour * 60.0);
+        if today.0 > self.0 {
+            today
+        } else {
+            GameTime(today.0 + MINUTES_PER_DAY)
+        }
+    }
+
+    /// Short timestamp such as "Day 3 14:05".
+    pub fn timestamp(&self) -> String {
+        let minutes = self.0.rem_euclid(MINUTES_PER_DAY) as u32;
+        format!("Day {} {:02}:{:02}", self.day(), minutes / 60, minutes % 60)
+    }
+
+    /// Prompt sentence such as "It is the afternoon of day 3 of spring (14:05)."
+    pub fn describe(&self) -> String {
+        let minutes = self.0.rem_euclid(MINUTES_PER_DAY) as u32;
+        let day_of_season = (self.day() - 1) % DAYS_PER_SEASON + 1;
+        format!(
+            "It is the {} of day {} of {} ({:02}:{:02}).",
+            self.part_of_day(),
+            day_of_season,
+            self.season().name(),
+            minutes / 60,
+            minutes % 60
+        )
+    }
+}
+
+/// The in-game calendar, advanced by game time.
+#[derive(Resource)]
+pub struct GameClock {
+    pub now: GameTime,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock {
+            now: GameTime(START_HOUR * 60.0),
+        }
+    }
+}
+
+impl GameClock {
+    /// Game days that pass during `seconds` of real time.
+    pub fn days_in(seconds: f32) -> f32 {
+        seconds * MINUTES_PER_SECOND / MINUTES_PER_DAY
+    }
+}
+
+/// The clock jumped ahead, e.g. while the player slept, without the frames in between.
+#[derive(Event)]
+pub struct TimeSkipped {
+    pub minutes: f32,
+}
+
+/// Full-screen overlay attached to the camera that darkens the scene at night.
+#[derive(Component)]
+pub struct NightOverlay;
+
+pub fn advance_clock(time: Res<Time>, mut clock: ResMut<GameClock>) {
+    clock.now.0 += time.delta_seconds() * MINUTES_PER_SECOND;
+}
+
+pub fn night_overlay_bundle() -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color: NIGHT_TINT.with_a(0.0),
+            custom_size: Some(Vec2::splat(100_000.0)),
+            ..default()
+        },
+        // in front of the world but within the camera's far plane
+        transform: Transform::from_xyz(0.0, 0.0, 900.0),
+        ..default()
+    }
+}
+
+pub fn update_lighting(
+    clock: Res<GameClock>,
+    mut overlays: Query<&mut Sprite, With<NightOverlay>>,
+) {
+    let darkness = clock.now.darkness();
+    for mut sprite in &mut overlays {
+        sprite.color = NIGHT_TINT.with_a(NIGHT_TINT.a() * darkness);
+    }
+}
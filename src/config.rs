@@ -0,0 +1,227 @@
+//! Tuning loaded in layers, each overriding the last: the defaults, a config file, environment
+//! variables and command line flags.
+
+use std::{env, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    AudioFxSettings, CharacterSettings, DialogSettings, FarmingSettings, HudSettings, NpcAiSettings,
+};
+
+pub const CONFIG_PATH: &str = "assets/config.json";
+
+/// Environment variables and the settings they override.
+const ENV_VARS: &[(&str, &str)] = &[
+    ("OPENAI_ENDPOINT", "dialog.endpoint"),
+    ("OPENAI_MODEL", "dialog.model"),
+    ("OPENAI_TEMPERATURE", "dialog.temperature"),
+    ("OPENAI_MAX_TOKENS", "dialog.max_tokens"),
+    ("CHARACTER_SPEED", "character.speed"),
+    ("HUNGER_PER_SECOND", "character.hunger_per_second"),
+    ("CHAT_COOLDOWN", "npc_ai.chat_cooldown"),
+    ("GROWTH_RATE", "farming.growth_per_day"),
+    ("MUSIC_VOLUME", "audio.music_volume"),
+    ("AMBIENCE_VOLUME", "audio.ambience_volume"),
+    ("EFFECTS_VOLUME", "audio.effects_volume"),
+];
+
+pub const USAGE: &str = "Options:
+  --headless             simulate the village without a window
+  --config FILE          read settings from FILE instead of assets/config.json
+  --set SECTION.KEY=VALUE
+                         override one setting, e.g. --set dialog.model=gpt-4
+  --print-config         print the resulting settings as JSON and exit";
+
+/// The settings of every plugin.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct GameConfig {
+    pub dialog: DialogSettings,
+    pub character: CharacterSettings,
+    pub npc_ai: NpcAiSettings,
+    pub farming: FarmingSettings,
+    pub audio: AudioFxSettings,
+    pub hud: HudSettings,
+}
+
+/// What the command line asked for besides the settings.
+#[derive(Default, Debug)]
+pub struct ConfigArgs {
+    pub config_path: Option<String>,
+    pub overrides: Vec<(String, String)>,
+    pub print_config: bool,
+    pub headless: bool,
+}
+
+impl ConfigArgs {
+    /// Reads the flags in `USAGE`, rejecting anything else so a typo doesn't quietly start the
+    /// game with the default settings.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = ConfigArgs::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    parsed.config_path = Some(args.next().ok_or("--config needs a file")?);
+                }
+                "--set" => {
+                    let setting = args.next().ok_or("--set needs SECTION.KEY=VALUE")?;
+                    let (key, value) = setting
+                        .split_once('=')
+                        .ok_or_else(|| format!("Expected SECTION.KEY=VALUE, got {}", setting))?;
+                    parsed.overrides.push((key.to_string(), value.to_string()));
+                }
+                "--print-config" => parsed.print_config = true,
+                "--headless" => parsed.headless = true,
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+impl GameConfig {
+    /// Layers the config file, environment variables and `args` over the defaults.
+    pub fn load(args: &ConfigArgs) -> Result<Self, String> {
+        let mut config = serde_json::to_value(GameConfig::default()).unwrap();
+
+        let path = args.config_path.as_deref().unwrap_or(CONFIG_PATH);
+        // only a file that was asked for by name has to exist
+        if args.config_path.is_some() || Path::new(path).exists() {
+            let file = fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| serde_json::from_str(&source).map_err(|e| e.to_string()))
+                .map_err(|e| format!("Could not load {}: {}", path, e))?;
+            merge(&mut config, file).map_err(|e| format!("{}: {}", path, e))?;
+        }
+
+        for (var, key) in ENV_VARS {
+            if let Ok(value) = env::var(var) {
+                set(&mut config, key, &value).map_err(|e| format!("{}: {}", var, e))?;
+            }
+        }
+
+        for (key, value) in &args.overrides {
+            set(&mut config, key, value).map_err(|e| format!("--set {}: {}", key, e))?;
+        }
+
+        let config: GameConfig =
+            serde_json::from_value(config).map_err(|e| format!("Invalid config: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects settings that parse but can't work, e.g. a plant spacing of 0.
+    pub fn validate(&self) -> Result<(), String> {
+        let rules = [
+            (
+                self.dialog.max_tokens >= 1,
+                "dialog.max_tokens must be >= 1",
+            ),
+            (
+                (0.0..=2.0).contains(&self.dialog.temperature),
+                "dialog.temperature must be between 0 and 2",
+            ),
+            (
+                self.dialog.request_timeout > 0.0,
+                "dialog.request_timeout must be > 0",
+            ),
+            (self.character.speed >= 0.0, "character.speed must be >= 0"),
+            (
+                self.character.hunger_per_second >= 0.0,
+                "character.hunger_per_second must be >= 0",
+            ),
+            (
+                self.npc_ai.chat_cooldown >= 0.0,
+                "npc_ai.chat_cooldown must be >= 0",
+            ),
+            (
+                self.npc_ai.max_concurrent_requests >= 1,
+                "npc_ai.max_concurrent_requests must be >= 1",
+            ),
+            (
+                self.npc_ai.stale_after > 0.0,
+                "npc_ai.stale_after must be > 0",
+            ),
+            (
+                self.npc_ai.near_player_distance >= 0.0,
+                "npc_ai.near_player_distance must be >= 0",
+            ),
+            (
+                self.npc_ai.summarize_after > self.npc_ai.keep_recent,
+                "npc_ai.summarize_after must be > npc_ai.keep_recent",
+            ),
+            (
+                self.farming.growth_per_day >= 0.0,
+                "farming.growth_per_day must be >= 0",
+            ),
+            (
+                self.farming.plant_spacing >= 1.0,
+                "farming.plant_spacing must be >= 1",
+            ),
+            (
+                self.audio.music_volume >= 0.0
+                    && self.audio.ambience_volume >= 0.0
+                    && self.audio.effects_volume >= 0.0,
+                "audio volumes must be >= 0",
+            ),
+        ];
+        match rules.into_iter().find(|(valid, _)| !valid) {
+            Some((_, rule)) => Err(rule.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// Inserts every plugin's settings, so the plugins added afterwards use them.
+    pub fn insert(self, app: &mut App) {
+        app.insert_resource(self.dialog)
+            .insert_resource(self.character)
+            .insert_resource(self.npc_ai)
+            .insert_resource(self.farming)
+            .insert_resource(self.audio)
+            .insert_resource(self.hud);
+    }
+}
+
+/// Copies the settings in `overlay`, shaped like the config, onto `config`. Each setting is
+/// replaced whole, so a file's `logit_bias` replaces the default one rather than adding to it.
+fn merge(config: &mut Value, overlay: Value) -> Result<(), String> {
+    let Value::Object(sections) = overlay else {
+        return Err("Expected an object of sections".to_string());
+    };
+    for (section, fields) in sections {
+        let Value::Object(fields) = fields else {
+            return Err(format!("Expected an object for {}", section));
+        };
+        for (field, value) in fields {
+            set_value(config, &section, &field, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn set_value(config: &mut Value, section: &str, field: &str, value: Value) -> Result<(), String> {
+    let setting = config
+        .get_mut(section)
+        .and_then(|fields| fields.get_mut(field))
+        .ok_or_else(|| format!("Unknown setting {}.{}", section, field))?;
+    *setting = value;
+    Ok(())
+}
+
+/// Sets the setting at `SECTION.KEY` from text, read as JSON where it parses, e.g. `0.5` or
+/// `["\n"]`, and as is for text settings like the model.
+fn set(config: &mut Value, key: &str, value: &str) -> Result<(), String> {
+    let (section, field) = key
+        .split_once('.')
+        .ok_or_else(|| format!("Expected SECTION.KEY, got {}", key))?;
+    let is_text = config[section][field].is_string();
+    let value = match serde_json::from_str(value) {
+        Ok(value) if !is_text => value,
+        _ => Value::String(value.to_string()),
+    };
+    set_value(config, section, field, value)
+}
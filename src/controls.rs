@@ -0,0 +1,336 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+use bevy::{app::AppExit, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+pub const SETTINGS_DIRECTORY: &str = "settings";
+pub const CONTROLS_PATH: &str = "settings/controls.json";
+
+/// Something the player can do, independent of the key or button bound to it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum InputAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Interact,
+    /// Focus the chat box, or send what was typed into it.
+    Chat,
+    PerceptionOverlay,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 7] = [
+        InputAction::MoveUp,
+        InputAction::MoveDown,
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Interact,
+        InputAction::Chat,
+        InputAction::PerceptionOverlay,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InputAction::MoveUp => "Move up",
+            InputAction::MoveDown => "Move down",
+            InputAction::MoveLeft => "Move left",
+            InputAction::MoveRight => "Move right",
+            InputAction::Interact => "Interact",
+            InputAction::Chat => "Chat",
+            InputAction::PerceptionOverlay => "Perception overlay",
+        }
+    }
+
+    fn default_bindings(&self) -> Vec<Binding> {
+        use Binding::{Button, Key};
+        match self {
+            InputAction::MoveUp => vec![
+                Key(KeyCode::KeyW),
+                Key(KeyCode::ArrowUp),
+                Button(GamepadButtonType::DPadUp),
+            ],
+            InputAction::MoveDown => vec![
+                Key(KeyCode::KeyS),
+                Key(KeyCode::ArrowDown),
+                Button(GamepadButtonType::DPadDown),
+            ],
+            InputAction::MoveLeft => vec![
+                Key(KeyCode::KeyA),
+                Key(KeyCode::ArrowLeft),
+                Button(GamepadButtonType::DPadLeft),
+            ],
+            InputAction::MoveRight => vec![
+                Key(KeyCode::KeyD),
+                Key(KeyCode::ArrowRight),
+                Button(GamepadButtonType::DPadRight),
+            ],
+            InputAction::Interact => vec![Key(KeyCode::Space), Button(GamepadButtonType::South)],
+            InputAction::Chat => vec![Key(KeyCode::Enter)],
+            InputAction::PerceptionOverlay => {
+                vec![Key(KeyCode::F3), Button(GamepadButtonType::Select)]
+            }
+        }
+    }
+}
+
+/// A keyboard key, or a button on any connected gamepad.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key).trim_start_matches("Key").to_string(),
+            Binding::Button(button) => format!("Pad {:?}", button),
+        }
+    }
+}
+
+/// Which keys and buttons trigger each action, persisted in `settings/controls.json`.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct Controls {
+    bindings: HashMap<InputAction, Vec<Binding>>,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            bindings: InputAction::ALL
+                .iter()
+                .map(|action| (*action, action.default_bindings()))
+                .collect(),
+        }
+    }
+}
+
+impl Controls {
+    /// Loads the player's bindings, keeping the defaults for actions the file doesn't mention.
+    pub fn load() -> Self {
+        let mut controls = Controls::default();
+        let saved = fs::read_to_string(CONTROLS_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str::<Controls>(&json).map_err(|e| e.to_string()));
+        match saved {
+            Ok(saved) => controls.bindings.extend(saved.bindings),
+            Err(e) => println!("Could not load {}: {}", CONTROLS_PATH, e),
+        }
+        controls
+    }
+
+    pub fn save(&self) {
+        let result = fs::create_dir_all(SETTINGS_DIRECTORY)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(self).map_err(|e| e.to_string()))
+            .and_then(|json| fs::write(CONTROLS_PATH, json).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("Saved controls to {}", CONTROLS_PATH),
+            Err(e) => println!("Could not save controls: {}", e),
+        }
+    }
+
+    pub fn bindings(&self, action: InputAction) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The first binding of `action`, for on-screen hints such as "Space: Harvest".
+    pub fn describe(&self, action: InputAction) -> String {
+        self.bindings(action)
+            .first()
+            .map_or("Unbound".to_string(), Binding::label)
+    }
+
+    /// Binds `binding` to `action`, taking it away from whichever action had it before.
+    fn bind(&mut self, action: InputAction, binding: Binding) {
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|existing| *existing != binding);
+        }
+        self.bindings.entry(action).or_default().push(binding);
+    }
+}
+
+/// The actions the player is performing this frame, read from the keyboard and gamepads.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<InputAction>,
+    just_pressed: HashSet<InputAction>,
+    /// Walking direction from the movement keys and the left stick, at most one long.
+    pub movement: Vec2,
+    /// Set while the chat box has keyboard focus, so typing doesn't move the player.
+    pub typing: bool,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// The action waiting for a new binding in the controls window, if any.
+#[derive(Resource, Default)]
+pub struct ControlsMenu {
+    listening: Option<InputAction>,
+}
+
+pub fn read_actions(
+    controls: Res<Controls>,
+    menu: Res<ControlsMenu>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut actions: ResMut<ActionState>,
+) {
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+    actions.movement = Vec2::ZERO;
+    if actions.typing || menu.listening.is_some() {
+        return;
+    }
+
+    let button_held = |button_type: GamepadButtonType, just: bool| {
+        gamepads.iter().any(|gamepad| {
+            let button = GamepadButton::new(gamepad, button_type);
+            if just {
+                gamepad_buttons.just_pressed(button)
+            } else {
+                gamepad_buttons.pressed(button)
+            }
+        })
+    };
+    for action in InputAction::ALL {
+        for binding in controls.bindings(action) {
+            let (pressed, just_pressed) = match *binding {
+                Binding::Key(key) => (
+                    keyboard_input.pressed(key),
+                    keyboard_input.just_pressed(key),
+                ),
+                Binding::Button(button) => (button_held(button, false), button_held(button, true)),
+            };
+            if pressed {
+                actions.pressed.insert(action);
+            }
+            if just_pressed {
+                actions.just_pressed.insert(action);
+            }
+        }
+    }
+
+    let mut movement = Vec2::ZERO;
+    for (action, direction) in [
+        (InputAction::MoveUp, Vec2::Y),
+        (InputAction::MoveDown, Vec2::NEG_Y),
+        (InputAction::MoveLeft, Vec2::NEG_X),
+        (InputAction::MoveRight, Vec2::X),
+    ] {
+        if actions.pressed(action) {
+            movement += direction;
+        }
+    }
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| {
+            gamepad_axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        movement += Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+    }
+    actions.movement = movement.clamp_length_max(1.0);
+}
+
+/// Quits the game on Escape, unless Escape is leaving the chat box or cancelling a rebind. Runs
+/// before the chat box and controls window see this frame's input.
+pub fn quit_on_escape(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    menu: Res<ControlsMenu>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) && !actions.typing && menu.listening.is_none() {
+        exit.send(AppExit);
+    }
+}
+
+/// Lists every action with its bindings. Clicking a binding removes it, and "Add" binds the next
+/// key or gamepad button pressed; Escape cancels.
+pub fn controls_menu(
+    mut contexts: EguiContexts,
+    mut controls: ResMut<Controls>,
+    mut menu: ResMut<ControlsMenu>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+) {
+    if let Some(action) = menu.listening {
+        let pressed = keyboard_input
+            .get_just_pressed()
+            .next()
+            .map(|key| Binding::Key(*key))
+            .or_else(|| {
+                gamepad_buttons
+                    .get_just_pressed()
+                    .next()
+                    .map(|button| Binding::Button(button.button_type))
+            });
+        match pressed {
+            Some(Binding::Key(KeyCode::Escape)) => menu.listening = None,
+            Some(binding) => {
+                controls.bind(action, binding);
+                controls.save();
+                menu.listening = None;
+            }
+            None => {}
+        }
+    }
+
+    let mut changed = false;
+    egui::Window::new("Controls")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").show(ui, |ui| {
+                for action in InputAction::ALL {
+                    ui.label(action.label());
+                    ui.horizontal(|ui| {
+                        for binding in controls.bindings(action).to_vec() {
+                            if ui.button(binding.label()).on_hover_text("Remove").clicked() {
+                                controls
+                                    .bindings
+                                    .entry(action)
+                                    .or_default()
+                                    .retain(|existing| *existing != binding);
+                                changed = true;
+                            }
+                        }
+                        if menu.listening == Some(action) {
+                            ui.label("Press a key or button...");
+                        } else if ui.button("Add").clicked() {
+                            menu.listening = Some(action);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+            if ui.button("Reset to defaults").clicked() {
+                *controls = Controls::default();
+                changed = true;
+            }
+        });
+    if changed {
+        controls.save();
+    }
+}
@@ -0,0 +1,348 @@
+//! The OpenAI API key: found once at startup, checked against the endpoint and kept out of logs.
+
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bevy::{
+    prelude::*,
+    tasks::{futures_lite::future, AsyncComputeTaskPool, Task},
+};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::dialog::DialogSettings;
+
+pub const KEY_VAR: &str = "OPENAI_API_KEY";
+/// Overrides where the plain key file is looked for.
+pub const KEY_FILE_VAR: &str = "OPENAI_API_KEY_FILE";
+/// Overrides where the encrypted key file is looked for.
+pub const ENCRYPTED_KEY_FILE_VAR: &str = "OPENAI_API_KEY_ENCRYPTED_FILE";
+/// Passphrase that unlocks the encrypted key file.
+pub const PASSPHRASE_VAR: &str = "BEVY_RPG_KEY_PASSPHRASE";
+
+const KEY_FILE: &str = "api_key";
+const ENCRYPTED_KEY_FILE: &str = "api_key.enc";
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+/// A secret that never shows up in `Debug` output, so it can't slip into a log by accident.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Secret(secret.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KeySource {
+    Env,
+    File(PathBuf),
+    EncryptedFile(PathBuf),
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeySource::Env => write!(f, "the {} environment variable", KEY_VAR),
+            KeySource::File(path) => write!(f, "{}", path.display()),
+            KeySource::EncryptedFile(path) => write!(f, "{} (encrypted)", path.display()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KeyStatus {
+    Missing,
+    /// Not probed yet, or the endpoint has nowhere to probe.
+    Unchecked,
+    Checking,
+    Valid,
+    /// The endpoint turned the key down.
+    Rejected(String),
+    /// The probe couldn't reach the endpoint, so the key may still be fine.
+    Unreachable(String),
+}
+
+impl KeyStatus {
+    /// Whether prompts are worth sending with the key.
+    pub fn is_usable(&self) -> bool {
+        !matches!(self, KeyStatus::Missing | KeyStatus::Rejected(_))
+    }
+}
+
+/// The API key the dialog backend sends with every request, and what is known about it.
+#[derive(Resource, Debug)]
+pub struct Credentials {
+    key: Option<Secret>,
+    pub source: Option<KeySource>,
+    pub status: KeyStatus,
+    /// Key files that were found but couldn't be used, and why.
+    pub problems: Vec<String>,
+}
+
+impl Credentials {
+    pub fn from_key(key: impl Into<String>) -> Self {
+        Credentials {
+            key: Some(Secret::new(key)),
+            source: Some(KeySource::Env),
+            status: KeyStatus::Unchecked,
+            problems: vec![],
+        }
+    }
+
+    pub fn missing() -> Self {
+        Credentials {
+            key: None,
+            source: None,
+            status: KeyStatus::Missing,
+            problems: vec![],
+        }
+    }
+
+    /// Looks for the key in `OPENAI_API_KEY`, then in a key file readable only by its owner, then
+    /// in an encrypted key file unlocked by `BEVY_RPG_KEY_PASSPHRASE`.
+    pub fn resolve() -> Self {
+        let mut credentials = Credentials::missing();
+        if let Some(key) = env::var(KEY_VAR).ok().filter(|key| !key.trim().is_empty()) {
+            credentials.found(key, KeySource::Env);
+        } else if let Some(path) = key_path(KEY_FILE_VAR, KEY_FILE).filter(|path| path.exists()) {
+            match read_key_file(&path) {
+                Ok(key) => credentials.found(key, KeySource::File(path)),
+                Err(e) => credentials.problem(&path, e),
+            }
+        }
+        if credentials.key.is_none() {
+            if let Some(path) = encrypted_key_path().filter(|path| path.exists()) {
+                match read_encrypted_key_file(&path) {
+                    Ok(key) => credentials.found(key, KeySource::EncryptedFile(path)),
+                    Err(e) => credentials.problem(&path, e),
+                }
+            }
+        }
+        match &credentials.source {
+            Some(source) => println!("Using the OpenAI API key from {}", source),
+            None => println!("No OpenAI API key found, NPCs will stay quiet"),
+        }
+        credentials
+    }
+
+    fn found(&mut self, key: String, source: KeySource) {
+        self.key = Some(Secret::new(key.trim()));
+        self.source = Some(source);
+        self.status = KeyStatus::Unchecked;
+    }
+
+    fn problem(&mut self, path: &Path, e: String) {
+        let problem = format!("Could not load {}: {}", path.display(), e);
+        println!("{}", problem);
+        self.problems.push(problem);
+    }
+
+    pub fn key(&self) -> Option<&Secret> {
+        self.key.as_ref()
+    }
+
+    /// `text` with the key blanked out, for logging request and response bodies.
+    pub fn redact(&self, text: &str) -> String {
+        match &self.key {
+            Some(key) if !key.expose().is_empty() => text.replace(key.expose(), "[redacted]"),
+            _ => text.to_string(),
+        }
+    }
+}
+
+/// Where a key file is, from `var` or else in the user's config directory.
+fn key_path(var: &str, file_name: &str) -> Option<PathBuf> {
+    if let Ok(path) = env::var(var) {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|_| env::var("APPDATA").map(PathBuf::from))
+        .ok()?;
+    Some(config_dir.join("bevy_rpg").join(file_name))
+}
+
+fn read_key_file(path: &Path) -> Result<String, String> {
+    check_permissions(path)?;
+    let key = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if key.trim().is_empty() {
+        return Err("the file is empty".to_string());
+    }
+    Ok(key)
+}
+
+/// Refuses key files that other users could read.
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .map_err(|e| e.to_string())?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "other users can read it (mode {:o}), run chmod 600 on it",
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+/// An API key sealed with a key derived from a passphrase.
+#[derive(Serialize, Deserialize)]
+struct EncryptedKey {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+/// The contents of an encrypted key file holding `key`, unlocked by `passphrase`.
+pub fn encrypt_key(key: &str, passphrase: &str) -> String {
+    let mut salt = [0; 16];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(passphrase, &salt)
+        .encrypt(&nonce, key.trim().as_bytes())
+        .expect("encryption does not fail for short keys");
+    let encrypted = EncryptedKey {
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    };
+    serde_json::to_string_pretty(&encrypted).unwrap()
+}
+
+/// The key in the contents of an encrypted key file.
+pub fn decrypt_key(contents: &str, passphrase: &str) -> Result<String, String> {
+    let encrypted: EncryptedKey = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    let decode = |field: &str| BASE64.decode(field).map_err(|e| e.to_string());
+    let (salt, nonce, ciphertext) = (
+        decode(&encrypted.salt)?,
+        decode(&encrypted.nonce)?,
+        decode(&encrypted.ciphertext)?,
+    );
+    if nonce.len() != 12 {
+        return Err("the nonce is not 12 bytes".to_string());
+    }
+    let key = cipher(passphrase, &salt)
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "wrong passphrase or a damaged file".to_string())?;
+    String::from_utf8(key).map_err(|e| e.to_string())
+}
+
+fn read_encrypted_key_file(path: &Path) -> Result<String, String> {
+    let passphrase =
+        env::var(PASSPHRASE_VAR).map_err(|_| format!("{} is not set", PASSPHRASE_VAR))?;
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    decrypt_key(&contents, &passphrase)
+}
+
+/// Where the game looks for the encrypted key file.
+pub fn encrypted_key_path() -> Option<PathBuf> {
+    key_path(ENCRYPTED_KEY_FILE_VAR, ENCRYPTED_KEY_FILE)
+}
+
+/// The request checking whether the endpoint accepts the key.
+#[derive(Resource)]
+pub struct KeyProbe(Task<KeyStatus>);
+
+/// Where to probe the key cheaply: the model list next to the chat completions endpoint.
+fn models_url(endpoint: &str) -> Option<String> {
+    endpoint
+        .strip_suffix("/chat/completions")
+        .map(|base| format!("{}/models", base))
+}
+
+pub fn start_key_probe(
+    mut commands: Commands,
+    mut credentials: ResMut<Credentials>,
+    settings: Res<DialogSettings>,
+) {
+    if !settings.validate_key {
+        return;
+    }
+    let (Some(key), Some(url)) = (credentials.key.clone(), models_url(&settings.endpoint)) else {
+        return;
+    };
+    credentials.status = KeyStatus::Checking;
+    let timeout = Duration::from_secs_f32(settings.request_timeout.max(0.0));
+    let task = AsyncComputeTaskPool::get().spawn(async_compat::Compat::new(async move {
+        let response = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_default()
+            .get(url)
+            .bearer_auth(key.expose())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => KeyStatus::Valid,
+            Ok(response) if matches!(response.status().as_u16(), 401 | 403) => {
+                KeyStatus::Rejected(response.status().to_string())
+            }
+            Ok(response) => KeyStatus::Unreachable(response.status().to_string()),
+            Err(e) => KeyStatus::Unreachable(e.to_string()),
+        }
+    }));
+    commands.insert_resource(KeyProbe(task));
+}
+
+pub fn poll_key_probe(
+    mut commands: Commands,
+    probe: Option<ResMut<KeyProbe>>,
+    mut credentials: ResMut<Credentials>,
+) {
+    let Some(mut probe) = probe else {
+        return;
+    };
+    let Some(status) = future::block_on(future::poll_once(&mut probe.0)) else {
+        return;
+    };
+    match &status {
+        KeyStatus::Valid => println!("The OpenAI API key works"),
+        KeyStatus::Rejected(reason) => println!("The OpenAI API key was rejected: {}", reason),
+        KeyStatus::Unreachable(reason) => {
+            println!(
+                "Could not check the OpenAI API key: {}",
+                credentials.redact(reason)
+            )
+        }
+        _ => {}
+    }
+    credentials.status = status;
+    commands.remove_resource::<KeyProbe>();
+}
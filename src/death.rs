@@ -0,0 +1,226 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    clock::GameClock,
+    dialog::DialogRequest,
+    interaction::{ground_item_bundle, GroundItem},
+    perception::Perception,
+    save::LoadGame,
+    storyline::Storylines,
+    Action, Character, Player, StartPos, NPC,
+};
+
+/// Seconds until word of a death reaches everyone else in the village.
+const NEWS_DELAY: f32 = 60.0;
+const CORPSE_COLOR: Color = Color::rgb(0.45, 0.42, 0.4);
+/// Whatever the dead were carrying is left on the ground, drawn as a ripe plant.
+const DROPPED_ITEM_TEXTURE: &str = "textures/plants/stage3.png";
+
+/// A character that has died. Their body stays in the world until they respawn or a save is loaded.
+#[derive(Component)]
+pub struct Dead {
+    pub cause: String,
+    /// Elapsed game time when they died, in seconds.
+    pub time_of_death: f32,
+}
+
+#[derive(Event)]
+pub struct CharacterDied {
+    pub name: String,
+    pub position: Vec2,
+    pub cause: String,
+}
+
+struct PendingNews {
+    remaining: f32,
+    name: String,
+    cause: String,
+    witnesses: Vec<String>,
+}
+
+/// Deaths that only the witnesses know about so far.
+#[derive(Resource, Default)]
+pub struct DeathNews {
+    pending: Vec<PendingNews>,
+}
+
+pub fn lay_down(transform: &mut Transform, sprite: &mut Sprite) {
+    transform.rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+    sprite.color = CORPSE_COLOR;
+}
+
+pub fn stand_up(transform: &mut Transform, sprite: &mut Sprite) {
+    transform.rotation = Quat::IDENTITY;
+    sprite.color = Color::WHITE;
+}
+
+/// Turns newly dead characters into corpses, silences them and drops what they carried.
+pub fn lay_down_corpses(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut corpses: Query<
+        (
+            Entity,
+            &mut Character,
+            &mut Transform,
+            &mut Sprite,
+            &Children,
+        ),
+        Added<Dead>,
+    >,
+    mut text_query: Query<&mut Text>,
+) {
+    for (entity, mut character, mut transform, mut sprite, children) in &mut corpses {
+        lay_down(&mut transform, &mut sprite);
+        for (index, (item, count)) in character.items.drain(..).enumerate() {
+            let offset = Vec2::new(30.0 * (index as f32 + 1.0), -20.0);
+            commands.spawn((
+                GroundItem { item, count },
+                ground_item_bundle(
+                    asset_server.load(DROPPED_ITEM_TEXTURE),
+                    transform.translation.xy() + offset,
+                ),
+            ));
+        }
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                text.sections[0].value = "".to_string();
+            }
+        }
+        commands.entity(entity).remove::<DialogRequest>();
+    }
+}
+
+/// NPCs who saw or heard a death remember it immediately and everyone else hears about it a
+/// while later.
+pub fn spread_death_news(
+    time: Res<Time>,
+    clock: Res<GameClock>,
+    mut deaths: EventReader<CharacterDied>,
+    mut news: ResMut<DeathNews>,
+    mut npcs: Query<(&mut NPC, &Character, &Perception), Without<Dead>>,
+) {
+    for death in deaths.read() {
+        let mut witnesses = vec![];
+        for (mut npc, character, perception) in &mut npcs {
+            let witnessed = if perception.visible.contains(&death.name) {
+                format!("You saw {} die of {}.", death.name, death.cause)
+            } else if perception.audible.contains(&death.name) {
+                format!("You heard {} die of {} nearby.", death.name, death.cause)
+            } else {
+                continue;
+            };
+            npc.remember(clock.now, character.name.clone(), Action::Event(witnessed));
+            witnesses.push(character.name.clone());
+        }
+        news.pending.push(PendingNews {
+            remaining: NEWS_DELAY,
+            name: death.name.clone(),
+            cause: death.cause.clone(),
+            witnesses,
+        });
+    }
+
+    for pending in &mut news.pending {
+        pending.remaining -= time.delta_seconds();
+        if pending.remaining > 0.0 {
+            continue;
+        }
+        for (mut npc, character, _) in &mut npcs {
+            if !pending.witnesses.contains(&character.name) {
+                npc.remember(
+                    clock.now,
+                    character.name.clone(),
+                    Action::Event(format!(
+                        "You heard that {} died of {}.",
+                        pending.name, pending.cause
+                    )),
+                );
+            }
+        }
+    }
+    news.pending.retain(|pending| pending.remaining > 0.0);
+}
+
+pub fn respawn(
+    commands: &mut Commands,
+    entity: Entity,
+    character: &mut Character,
+    transform: &mut Transform,
+    sprite: &mut Sprite,
+    start_pos: Option<&StartPos>,
+) {
+    character.saturation = Character::default().saturation;
+    character.actions.clear();
+    transform.translation = start_pos
+        .map(|start_pos| start_pos.0)
+        .unwrap_or_default()
+        .extend(transform.translation.z);
+    stand_up(transform, sprite);
+    commands.entity(entity).remove::<Dead>();
+}
+
+pub fn game_over_system(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut player: Query<
+        (
+            Entity,
+            &mut Character,
+            &mut Transform,
+            &mut Sprite,
+            &Dead,
+            Option<&StartPos>,
+        ),
+        With<Player>,
+    >,
+    others: Query<(&Character, &Dead), Without<Player>>,
+    storylines: Res<Storylines>,
+    mut load_game: EventWriter<LoadGame>,
+) {
+    let Ok((entity, mut character, mut transform, mut sprite, dead, start_pos)) =
+        player.get_single_mut()
+    else {
+        return;
+    };
+    egui::Window::new("Game over")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading(format!("{} died of {}", character.name, dead.cause));
+            ui.label(format!(
+                "You survived for {:.0} minutes.",
+                dead.time_of_death / 60.0
+            ));
+            for (other, other_dead) in &others {
+                ui.label(format!("{} died of {}.", other.name, other_dead.cause));
+            }
+            let resolved = storylines
+                .storylines
+                .iter()
+                .filter(|storyline| storyline.resolution.is_some())
+                .count();
+            ui.label(format!(
+                "Village conflicts resolved: {}/{}",
+                resolved,
+                storylines.storylines.len()
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Respawn").clicked() {
+                    respawn(
+                        &mut commands,
+                        entity,
+                        &mut character,
+                        &mut transform,
+                        &mut sprite,
+                        start_pos,
+                    );
+                }
+                if ui.button("Load save").clicked() {
+                    load_game.send(LoadGame);
+                }
+            });
+        });
+}
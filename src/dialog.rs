@@ -0,0 +1,509 @@
+use std::{collections::HashMap, env, time::Duration};
+
+use bevy::{
+    prelude::*,
+    tasks::{futures_lite::future, AsyncComputeTaskPool, Task},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    credentials::{self, Credentials},
+    GameSet,
+};
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DialogSettings {
+    /// Ask the model for a JSON object with typed speech/emote/task fields instead of free text.
+    pub structured_output: bool,
+    /// Chat completions endpoint of an OpenAI compatible API.
+    pub endpoint: String,
+    /// Model used unless an experiment variant picks another one.
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    /// Token ids mapped to how much more or less likely the model should pick them.
+    pub logit_bias: HashMap<u32, f32>,
+    /// Sequences that end a free text reply. Structured replies are never cut short.
+    pub stop: Vec<String>,
+    /// Check at startup that the endpoint accepts the API key.
+    pub validate_key: bool,
+    /// Seconds to wait for the endpoint before a request fails.
+    pub request_timeout: f32,
+    /// Overrides for each kind of thought, falling back on the settings above.
+    pub bark: ModelProfile,
+    pub conversation: ModelProfile,
+    pub planning: ModelProfile,
+    pub summary: ModelProfile,
+}
+
+impl Default for DialogSettings {
+    fn default() -> Self {
+        DialogSettings {
+            structured_output: false,
+            endpoint: "https://api.openai.com/v1/chat/completions".to_string(),
+            model: "gpt-3.5-turbo".to_string(),
+            temperature: 1.0,
+            max_tokens: 64,
+            logit_bias: HashMap::from([(9, -5.0)]),
+            stop: vec!["\n".to_string()],
+            validate_key: true,
+            request_timeout: 30.0,
+            bark: ModelProfile {
+                temperature: Some(1.1),
+                max_tokens: Some(24),
+                ..default()
+            },
+            conversation: ModelProfile::default(),
+            planning: ModelProfile {
+                temperature: Some(0.7),
+                ..default()
+            },
+            summary: ModelProfile {
+                temperature: Some(0.3),
+                max_tokens: Some(160),
+                ..default()
+            },
+        }
+    }
+}
+
+impl DialogSettings {
+    /// The defaults, pointed at the endpoint in `OPENAI_ENDPOINT` if set, e.g. a local mock server.
+    pub fn from_env() -> Self {
+        let mut settings = DialogSettings::default();
+        if let Ok(endpoint) = env::var("OPENAI_ENDPOINT") {
+            settings.endpoint = endpoint;
+        }
+        settings
+    }
+
+    /// The endpoint, model, temperature and token limit to use for `role`.
+    pub fn profile(&self, role: ModelRole) -> ResolvedProfile {
+        let profile = match role {
+            ModelRole::Bark => &self.bark,
+            ModelRole::Conversation => &self.conversation,
+            ModelRole::Planning => &self.planning,
+            ModelRole::Summary => &self.summary,
+        };
+        ResolvedProfile {
+            endpoint: profile.endpoint.clone().unwrap_or(self.endpoint.clone()),
+            model: profile.model.clone().unwrap_or(self.model.clone()),
+            temperature: profile.temperature.unwrap_or(self.temperature),
+            max_tokens: profile.max_tokens.unwrap_or(self.max_tokens),
+        }
+    }
+}
+
+/// What an NPC is thinking about, which decides the model profile and tools of its request.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelRole {
+    /// A line said in passing with nobody around to answer.
+    Bark,
+    /// Talking with someone nearby.
+    #[default]
+    Conversation,
+    /// Picking what to do next.
+    Planning,
+    /// Condensing old memories.
+    Summary,
+}
+
+impl ModelRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModelRole::Bark => "bark",
+            ModelRole::Conversation => "conversation",
+            ModelRole::Planning => "planning",
+            ModelRole::Summary => "summary",
+        }
+    }
+
+    /// Whether the NPC may change its task or plan in this kind of thought.
+    pub fn uses_tools(&self) -> bool {
+        matches!(self, ModelRole::Conversation | ModelRole::Planning)
+    }
+
+    /// Whether the reply is what the NPC says or does, rather than plain text like a summary.
+    pub fn is_speech(&self) -> bool {
+        !matches!(self, ModelRole::Summary)
+    }
+}
+
+/// Settings of one `ModelRole`, each unset one taken from the general dialog settings.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct ModelProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ResolvedProfile {
+    pub endpoint: String,
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+}
+
+/// Sends NPC prompts to a language model over HTTP and hands the replies back as events.
+pub struct DialogBackendPlugin;
+
+impl Plugin for DialogBackendPlugin {
+    fn build(&self, app: &mut App) {
+        crate::configure_sets(app);
+        crate::insert_if_missing(app, DialogSettings::from_env);
+        crate::insert_if_missing(app, Credentials::resolve);
+        app.add_event::<DialogPrompt>()
+            .add_event::<DialogReply>()
+            .add_event::<DialogCancel>()
+            .add_systems(Startup, credentials::start_key_probe)
+            .add_systems(
+                Update,
+                (
+                    credentials::poll_key_probe,
+                    send_prompts,
+                    poll_dialog_requests,
+                )
+                    .in_set(GameSet::Simulation),
+            );
+    }
+}
+
+/// Asks the dialog backend what an NPC says or does next.
+#[derive(Event, Clone)]
+pub struct DialogPrompt {
+    pub npc: Entity,
+    pub messages: Vec<OpenAIMessage>,
+    pub role: ModelRole,
+    /// Tasks the `set_task` tool offers, the built-in ones when empty.
+    pub tasks: Vec<String>,
+    /// Overrides of the profile's model and temperature, e.g. from an experiment variant.
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    /// Experiment variant the prompt was made with.
+    pub variant: Option<String>,
+    /// Number of the prompt, handed back in the reply to tell it from replies to older prompts.
+    pub request: u64,
+}
+
+/// The backend's answer to a `DialogPrompt`, with no message when the request failed.
+#[derive(Event)]
+pub struct DialogReply {
+    pub npc: Entity,
+    pub message: Option<OpenAIMessage>,
+    pub role: ModelRole,
+    pub variant: Option<String>,
+    pub request: u64,
+}
+
+/// Drops the NPC's request in flight without a reply, e.g. because the NPC fell asleep.
+#[derive(Event, Clone, Copy)]
+pub struct DialogCancel {
+    pub npc: Entity,
+}
+
+/// A request to the model that hasn't been answered yet.
+#[derive(Component)]
+pub struct DialogRequest {
+    task: Task<Option<OpenAIMessage>>,
+    role: ModelRole,
+    /// Experiment variant the request was made with.
+    variant: Option<String>,
+    request: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIMessage {
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OpenAIToolCall>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIToolFunction {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAITool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: OpenAIToolFunction,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIRequest {
+    pub messages: Vec<OpenAIMessage>,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<u32, f32>>,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    pub top_p: f32,
+    pub frequency_penalty: f32,
+    pub presence_penalty: f32,
+    pub stop: Vec<String>,
+    // the API refuses an empty list of tools
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<OpenAITool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIFunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: OpenAIFunctionCall,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIChoice {
+    pub message: OpenAIMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIResponse {
+    pub choices: Vec<OpenAIChoice>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIError {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIErrorResponse {
+    pub error: OpenAIError,
+}
+
+/// Regions NPCs may be sent to.
+const DESTINATIONS: [&str; 4] = [
+    "Theo's Family Farm",
+    "Bill's Farm",
+    "Steve's Farm",
+    "Jacob's Farm",
+];
+
+/// Tasks every NPC knows without a behaviour tree.
+pub const BUILT_IN_TASKS: [&str; 3] = ["idle", "farming", "traveling"];
+
+/// Lets the model change what the NPC is doing to one of `tasks`.
+fn set_task_tool(tasks: &[String]) -> OpenAITool {
+    let tasks = if tasks.is_empty() {
+        BUILT_IN_TASKS.map(str::to_string).to_vec()
+    } else {
+        tasks.to_vec()
+    };
+    OpenAITool {
+        tool_type: "function".to_string(),
+        function: OpenAIToolFunction {
+            name: "set_task".to_string(),
+            description: "Change what you are currently doing. destination parameter should be used when task is traveling".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "task": {"type": "string", "enum": tasks},
+                    "destination": {"type": "string", "enum": DESTINATIONS},
+                },
+                "required": ["task"],
+            }),
+        },
+    }
+}
+
+/// Lets the model give the NPC a goal and the steps to reach it, see `plan::PlanStep`.
+fn set_plan_tool() -> OpenAITool {
+    OpenAITool {
+        tool_type: "function".to_string(),
+        function: OpenAIToolFunction {
+            name: "set_plan".to_string(),
+            description: "Make a plan of a few steps toward a goal, replacing your current plan. destination is used by travel steps, person and topic by talk steps, minutes by farm and wait steps".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "goal": {"type": "string"},
+                    "steps": {
+                        "type": "array",
+                        "maxItems": crate::plan::MAX_STEPS,
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "action": {"type": "string", "enum": ["travel", "farm", "talk", "return_home", "wait"]},
+                                "destination": {"type": "string", "enum": DESTINATIONS},
+                                "person": {"type": "string"},
+                                "topic": {"type": "string"},
+                                "minutes": {"type": "number"},
+                            },
+                            "required": ["action"],
+                        },
+                    },
+                },
+                "required": ["goal", "steps"],
+            }),
+        },
+    }
+}
+
+/// Drops cancelled requests, then starts an HTTP request for every prompt, replacing any request
+/// the NPC still had running. Without a usable API key every prompt fails straight away.
+fn send_prompts(
+    mut commands: Commands,
+    settings: Res<DialogSettings>,
+    credentials: Res<Credentials>,
+    mut cancels: EventReader<DialogCancel>,
+    mut prompts: EventReader<DialogPrompt>,
+    mut replies: EventWriter<DialogReply>,
+) {
+    for cancel in cancels.read() {
+        if let Some(mut entity) = commands.get_entity(cancel.npc) {
+            entity.remove::<DialogRequest>();
+        }
+    }
+    let thread_pool = AsyncComputeTaskPool::get();
+    for prompt in prompts.read() {
+        let Some(key) = credentials
+            .key()
+            .filter(|_| credentials.status.is_usable())
+            .cloned()
+        else {
+            replies.send(DialogReply {
+                npc: prompt.npc,
+                message: None,
+                role: prompt.role,
+                variant: prompt.variant.clone(),
+                request: prompt.request,
+            });
+            continue;
+        };
+        let redact = {
+            let key = key.clone();
+            move |text: &str| text.replace(key.expose(), "[redacted]")
+        };
+        let profile = settings.profile(prompt.role);
+        let structured_output = settings.structured_output && prompt.role.is_speech();
+        let endpoint = profile.endpoint;
+        let request_tag = match &prompt.variant {
+            Some(variant) => format!(" ({}, {})", prompt.role.name(), variant),
+            None => format!(" ({})", prompt.role.name()),
+        };
+        let request_body = OpenAIRequest {
+            messages: prompt.messages.clone(),
+            model: prompt.model.clone().unwrap_or(profile.model),
+            logit_bias: Some(settings.logit_bias.clone()).filter(|bias| !bias.is_empty()),
+            temperature: prompt.temperature.unwrap_or(profile.temperature),
+            max_tokens: profile.max_tokens,
+            top_p: 1.0,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            // a newline would cut a JSON reply or a summary short
+            stop: if structured_output || !prompt.role.is_speech() {
+                vec![]
+            } else {
+                settings.stop.clone()
+            },
+            tools: if prompt.role.uses_tools() {
+                vec![set_task_tool(&prompt.tasks), set_plan_tool()]
+            } else {
+                vec![]
+            },
+            response_format: structured_output.then(|| serde_json::json!({"type": "json_object"})),
+        };
+        let timeout = Duration::from_secs_f32(settings.request_timeout.max(0.0));
+        let task = thread_pool.spawn(async_compat::Compat::new(async move {
+            let body = serde_json::to_string(&request_body).unwrap_or_default();
+            println!("Request body{}: {:?}", request_tag, redact(&body));
+
+            let client = reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .unwrap_or_default();
+            let response = client
+                .post(endpoint)
+                .bearer_auth(key.expose())
+                .json(&request_body)
+                .send()
+                .await;
+            let response_text = match response {
+                Ok(response) => response.text().await,
+                Err(e) => Err(e),
+            };
+            let response_text = match response_text {
+                Ok(response_text) => response_text,
+                Err(e) => {
+                    println!("Request failed: {}", redact(&e.to_string()));
+                    return None;
+                }
+            };
+            let res: OpenAIResponse = match serde_json::from_str(&response_text) {
+                Ok(res) => res,
+                Err(e) => {
+                    if serde_json::from_str::<OpenAIErrorResponse>(&response_text).is_ok() {
+                        println!("Error: {:?}", redact(&response_text));
+                    } else {
+                        println!(
+                            "Could not parse response ({}): {}",
+                            e,
+                            redact(&response_text)
+                        );
+                    }
+                    return None;
+                }
+            };
+            println!("Response: {:?}", redact(&response_text));
+            let choice = res.choices.into_iter().next();
+            if choice.is_none() {
+                println!("The response has no choices");
+            }
+            choice.map(|choice| choice.message)
+        }));
+        if let Some(mut entity) = commands.get_entity(prompt.npc) {
+            entity.insert(DialogRequest {
+                task,
+                role: prompt.role,
+                variant: prompt.variant.clone(),
+                request: prompt.request,
+            });
+        }
+    }
+}
+
+fn poll_dialog_requests(
+    mut commands: Commands,
+    mut requests: Query<(Entity, &mut DialogRequest)>,
+    mut replies: EventWriter<DialogReply>,
+) {
+    for (entity, mut request) in &mut requests {
+        if let Some(message) = future::block_on(future::poll_once(&mut request.task)) {
+            replies.send(DialogReply {
+                npc: entity,
+                message,
+                role: request.role,
+                variant: request.variant.take(),
+                request: request.request,
+            });
+            commands.entity(entity).remove::<DialogRequest>();
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::{collections::HashMap, env, fs, path::Path};
+
+use bevy::{app::AppExit, prelude::*};
+use itertools::Itertools;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{death::CharacterDied, Character, NPC};
+
+pub const REPORT_DIRECTORY: &str = "experiment_reports";
+
+/// An experiment loaded from a JSON file, e.g. `assets/experiments/inventory_context.json`.
+#[derive(Deserialize, Clone)]
+pub struct ExperimentDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub assignment: Assignment,
+    pub variants: Vec<ExperimentVariant>,
+    /// Game seconds to run before writing the report and exiting.
+    #[serde(default)]
+    pub duration_seconds: Option<f32>,
+    /// Speeds up game time so starvation outcomes show up in a reasonable run time.
+    #[serde(default)]
+    pub time_scale: Option<f32>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Assignment {
+    /// Every NPC is spread across the variants.
+    #[default]
+    PerNpc,
+    /// All NPCs in a run share one randomly picked variant.
+    PerSession,
+}
+
+/// Overrides applied to the requests of NPCs assigned to this variant.
+#[derive(Deserialize, Clone)]
+pub struct ExperimentVariant {
+    pub name: String,
+    #[serde(default)]
+    pub prompt_variant: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Serialize, Default, Clone)]
+pub struct VariantMetrics {
+    pub npcs: u32,
+    pub requests: u32,
+    pub failed_requests: u32,
+    /// Replies thrown away because the NPC's situation changed while waiting for them.
+    pub stale_replies: u32,
+    pub parse_failures: u32,
+    pub talks: u32,
+    pub emotes: u32,
+    pub tool_calls: u32,
+    pub starved: u32,
+}
+
+/// The variant an NPC's requests are made with.
+#[derive(Component, Deref)]
+pub struct AssignedVariant(pub String);
+
+#[derive(Resource, Default)]
+pub struct Experiment {
+    pub definition: Option<ExperimentDefinition>,
+    session_variant: Option<usize>,
+    metrics: HashMap<String, VariantMetrics>,
+    report_written: bool,
+}
+
+impl Experiment {
+    /// Loads the experiment named by the `EXPERIMENT` environment variable, if any.
+    pub fn from_env() -> Self {
+        let Ok(path) = env::var("EXPERIMENT") else {
+            return Experiment::default();
+        };
+        match Experiment::load(&path) {
+            Ok(experiment) => experiment,
+            Err(e) => {
+                println!("Could not load experiment {}: {}", path, e);
+                Experiment::default()
+            }
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let definition: ExperimentDefinition =
+            serde_json::from_str(&source).map_err(|e| e.to_string())?;
+        if definition.variants.is_empty() {
+            return Err("experiment has no variants".to_string());
+        }
+        let session_variant = (definition.assignment == Assignment::PerSession)
+            .then(|| rand::thread_rng().gen_range(0..definition.variants.len()));
+        let metrics = definition
+            .variants
+            .iter()
+            .map(|variant| (variant.name.clone(), VariantMetrics::default()))
+            .collect();
+        Ok(Experiment {
+            definition: Some(definition),
+            session_variant,
+            metrics,
+            report_written: false,
+        })
+    }
+
+    pub fn variant(&self, name: &str) -> Option<&ExperimentVariant> {
+        self.definition
+            .as_ref()?
+            .variants
+            .iter()
+            .find(|variant| variant.name == name)
+    }
+
+    /// Metrics to update for a request made with `variant`, if an experiment is running.
+    pub fn metrics_mut(&mut self, variant: Option<&str>) -> Option<&mut VariantMetrics> {
+        self.metrics.get_mut(variant?)
+    }
+
+    fn next_variant(&self) -> Option<&ExperimentVariant> {
+        let variants = &self.definition.as_ref()?.variants;
+        let index = self.session_variant.unwrap_or_else(|| {
+            // the least populated variant keeps per-NPC groups balanced and deterministic
+            (0..variants.len())
+                .min_by_key(|&i| self.metrics[&variants[i].name].npcs)
+                .unwrap()
+        });
+        variants.get(index)
+    }
+
+    fn write_report(&mut self, elapsed_seconds: f32) {
+        let Some(definition) = &self.definition else {
+            return;
+        };
+        if self.report_written {
+            return;
+        }
+        self.report_written = true;
+
+        let rows = definition
+            .variants
+            .iter()
+            .map(|variant| {
+                let metrics = self.metrics[&variant.name].clone();
+                let per_request = |count: u32| count as f32 / metrics.requests.max(1) as f32;
+                let npc_minutes = metrics.npcs as f32 * elapsed_seconds / 60.0;
+                ReportRow {
+                    variant: variant.name.clone(),
+                    parse_failure_rate: per_request(metrics.parse_failures),
+                    tool_call_rate: per_request(metrics.tool_calls),
+                    talks_per_npc_minute: if npc_minutes > 0.0 {
+                        metrics.talks as f32 / npc_minutes
+                    } else {
+                        0.0
+                    },
+                    metrics,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        println!(
+            "Experiment {} after {:.0}s",
+            definition.name, elapsed_seconds
+        );
+        println!(
+            "{:<20} {:>5} {:>8} {:>12} {:>10} {:>10} {:>8}",
+            "variant", "npcs", "requests", "parse fails", "tool rate", "talk/min", "starved"
+        );
+        for row in &rows {
+            println!(
+                "{:<20} {:>5} {:>8} {:>12} {:>10.2} {:>10.2} {:>8}",
+                row.variant,
+                row.metrics.npcs,
+                row.metrics.requests,
+                row.metrics.parse_failures,
+                row.tool_call_rate,
+                row.talks_per_npc_minute,
+                row.metrics.starved
+            );
+        }
+
+        let report = Report {
+            experiment: definition.name.clone(),
+            elapsed_seconds,
+            variants: rows,
+        };
+        let path = Path::new(REPORT_DIRECTORY).join(format!("{}.json", definition.name));
+        let result = fs::create_dir_all(REPORT_DIRECTORY)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(&report).map_err(|e| e.to_string()))
+            .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("Wrote experiment report to {}", path.display()),
+            Err(e) => println!("Could not write experiment report: {}", e),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ReportRow {
+    variant: String,
+    parse_failure_rate: f32,
+    tool_call_rate: f32,
+    talks_per_npc_minute: f32,
+    #[serde(flatten)]
+    metrics: VariantMetrics,
+}
+
+#[derive(Serialize)]
+struct Report {
+    experiment: String,
+    elapsed_seconds: f32,
+    variants: Vec<ReportRow>,
+}
+
+pub fn apply_time_scale(experiment: Res<Experiment>, mut time: ResMut<Time<Virtual>>) {
+    if let Some(time_scale) = experiment.definition.as_ref().and_then(|d| d.time_scale) {
+        time.set_relative_speed(time_scale);
+    }
+}
+
+pub fn assign_variants(
+    mut commands: Commands,
+    mut experiment: ResMut<Experiment>,
+    new_npcs: Query<(Entity, &Character), Added<NPC>>,
+) {
+    for (entity, character) in new_npcs
+        .iter()
+        .sorted_by_key(|(_, character)| character.name.clone())
+    {
+        let Some(variant) = experiment
+            .next_variant()
+            .map(|variant| variant.name.clone())
+        else {
+            return;
+        };
+        println!("{} is in experiment variant {}", character.name, variant);
+        experiment.metrics_mut(Some(&variant)).unwrap().npcs += 1;
+        commands.entity(entity).insert(AssignedVariant(variant));
+    }
+}
+
+/// Counts starvation deaths towards the variant the character was in.
+pub fn count_starvation(
+    mut deaths: EventReader<CharacterDied>,
+    mut experiment: ResMut<Experiment>,
+    characters: Query<(&Character, &AssignedVariant)>,
+) {
+    for death in deaths.read() {
+        if death.cause != "starvation" {
+            continue;
+        }
+        let variant = characters
+            .iter()
+            .find(|(character, _)| character.name == death.name)
+            .map(|(_, variant)| variant.as_str());
+        if let Some(metrics) = experiment.metrics_mut(variant) {
+            metrics.starved += 1;
+        }
+    }
+}
+
+pub fn finish_experiment(
+    time: Res<Time>,
+    mut experiment: ResMut<Experiment>,
+    mut exit_events: ResMut<Events<AppExit>>,
+) {
+    let Some(definition) = &experiment.definition else {
+        return;
+    };
+    let elapsed = time.elapsed_seconds();
+    let duration_reached = definition
+        .duration_seconds
+        .is_some_and(|duration| elapsed >= duration);
+    if !exit_events.is_empty() {
+        experiment.write_report(elapsed);
+    } else if duration_reached {
+        experiment.write_report(elapsed);
+        exit_events.send(AppExit);
+    }
+}
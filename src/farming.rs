@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{clock, navigation, GameSet};
+
+/// Tuning for the fields and the passing of time.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FarmingSettings {
+    /// Growth per game day, so a harvested plant is ripe again in under two days.
+    pub growth_per_day: f32,
+    /// Distance between plants sown by `fill_rect_with_plants`.
+    pub plant_spacing: f32,
+}
+
+impl Default for FarmingSettings {
+    fn default() -> Self {
+        FarmingSettings {
+            growth_per_day: 0.6,
+            plant_spacing: 60.0,
+        }
+    }
+}
+
+/// The farmland itself: the clock and day-night cycle, the map, regions and growing plants.
+pub struct FarmingPlugin;
+
+impl Plugin for FarmingPlugin {
+    fn build(&self, app: &mut App) {
+        crate::configure_sets(app);
+        crate::insert_if_missing(app, navigation::NavigationGrid::load);
+        app.init_resource::<FarmingSettings>()
+            .init_resource::<clock::GameClock>()
+            .add_event::<clock::TimeSkipped>()
+            .add_systems(
+                Update,
+                (
+                    clock::advance_clock.in_set(GameSet::Time),
+                    (update_plants, clock::update_lighting).in_set(GameSet::Simulation),
+                ),
+            );
+    }
+}
+
+#[derive(Component, Deref, DerefMut)]
+pub struct Plant {
+    pub growth: f32,
+}
+
+impl Default for Plant {
+    fn default() -> Self {
+        Plant { growth: 0.0 }
+    }
+}
+
+impl Plant {
+    pub const HARVEST_RANGE: f32 = 50.0;
+
+    pub fn is_grown(&self) -> bool {
+        self.growth >= 1.0
+    }
+
+    fn get_growth_stage(&self) -> u32 {
+        (self.growth * 3.0).floor() as u32
+    }
+
+    fn grow(&mut self, amount: f32) {
+        self.growth += amount;
+        if self.growth > 1.0 {
+            self.growth = 1.0;
+        }
+    }
+}
+
+/// A named area of the map, such as a farm, that NPCs can travel to and work in.
+#[derive(Component)]
+pub struct Region {
+    pub name: String,
+    pub range: Rect,
+}
+
+/// Sows plants at random stages of growth across `rect`.
+pub fn fill_rect_with_plants(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    settings: &FarmingSettings,
+    rect: Rect,
+) {
+    let mut rng = rand::thread_rng();
+    let spacing = settings.plant_spacing as usize;
+    for x in (rect.min.x as i32..=rect.max.x as i32).step_by(spacing) {
+        for y in (rect.min.y as i32..=rect.max.y as i32).step_by(spacing) {
+            commands.spawn((
+                Plant {
+                    growth: rng.gen_range(0.0..1.0),
+                },
+                SpriteBundle {
+                    texture: asset_server.load("textures/plants/stage1.png"),
+                    transform: Transform {
+                        translation: Vec3::new(x as f32, y as f32, 0.0),
+                        scale: Vec3::new(0.3, 0.3, 0.0),
+                        ..default()
+                    },
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+}
+
+fn update_plants(
+    mut query: Query<(&mut Plant, &mut Handle<Image>)>,
+    settings: Res<FarmingSettings>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut skips: EventReader<clock::TimeSkipped>,
+) {
+    // crops keep growing through skipped time
+    let skipped = skips.read().map(|skip| skip.minutes).sum::<f32>();
+    let days = clock::GameClock::days_in(time.delta_seconds()) + skipped / clock::MINUTES_PER_DAY;
+    for (mut plant, mut texture) in &mut query {
+        plant.grow(settings.growth_per_day * days);
+        *texture = asset_server.load::<Image>(format!(
+            "textures/plants/stage{}.png",
+            plant.get_growth_stage()
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPlugin};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock, controls,
+    credentials::{self, Credentials, KeyStatus},
+    death, interaction, perception, relationship, save, storyline, Action, Character, Player, NPC,
+};
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HudSettings {
+    /// Show the window listing every NPC's state and relationships.
+    pub show_inspector: bool,
+    pub show_journal: bool,
+}
+
+impl Default for HudSettings {
+    fn default() -> Self {
+        HudSettings {
+            show_inspector: true,
+            show_journal: true,
+        }
+    }
+}
+
+/// The on-screen interface: chat box, inspector, journal, interaction prompt, controls menu,
+/// perception overlay and game over screen. Needs a window, so leave it out of headless runs.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.init_resource::<HudSettings>()
+            .init_resource::<perception::PerceptionOverlay>()
+            .add_systems(
+                Update,
+                (
+                    ui_system,
+                    inspector_system.run_if(|settings: Res<HudSettings>| settings.show_inspector),
+                    interaction::prompt_system,
+                    controls::controls_menu,
+                    controls::quit_on_escape
+                        .before(ui_system)
+                        .before(controls::controls_menu),
+                    perception::toggle_overlay,
+                    perception::draw_overlay,
+                    storyline::journal_system
+                        .run_if(|settings: Res<HudSettings>| settings.show_journal),
+                    death::game_over_system,
+                    api_key_system,
+                ),
+            );
+    }
+}
+
+fn ui_system(
+    mut contexts: EguiContexts,
+    mut players: Query<(&mut Player, &mut Character)>,
+    clock: Res<clock::GameClock>,
+    mut actions: ResMut<controls::ActionState>,
+    mut save_game: EventWriter<save::SaveGame>,
+) {
+    for (mut player, mut character) in &mut players {
+        egui::Window::new("Chat box").show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "{}, {}",
+                clock.now.timestamp(),
+                clock.now.season().name()
+            ));
+            ui.add(egui::ProgressBar::new(character.saturation / 100.0).text("Saturation"));
+            ui.label("Inventory");
+            for (item, count) in &character.items {
+                ui.label(format!("{}: {}", item, count));
+            }
+            let text_box = ui.text_edit_singleline(&mut player.text_box);
+            // egui drops focus when Enter is pressed in a single line text box
+            let entered = text_box.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if (entered || ui.button("Submit").clicked()) && !player.text_box.is_empty() {
+                character
+                    .actions
+                    .push(Action::Talk(player.text_box.clone()));
+                player.text_box = "".to_string();
+            }
+            if actions.just_pressed(controls::InputAction::Chat) {
+                text_box.request_focus();
+            }
+            actions.typing = text_box.has_focus();
+            if ui.button("Save").clicked() {
+                save_game.send(save::SaveGame);
+            }
+        });
+    }
+}
+
+fn inspector_system(
+    mut contexts: EguiContexts,
+    npcs: Query<(&NPC, &Character)>,
+    relationships: Res<relationship::Relationships>,
+) {
+    egui::Window::new("Inspector")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for (npc, character) in npcs.iter().sorted_by_key(|(_, character)| &character.name) {
+                ui.collapsing(&character.name, |ui| {
+                    ui.label(npc.state.get_context());
+                    if let Some(plan) = &npc.plan {
+                        ui.label(format!("Goal: {}", plan.goal));
+                        for (index, step) in plan.steps.iter().enumerate() {
+                            let marker = if index == plan.current { ">" } else { " " };
+                            ui.label(format!("{} {}. {}", marker, index + 1, step.describe()));
+                        }
+                    }
+                    ui.add(egui::ProgressBar::new(character.saturation / 100.0).text("Saturation"));
+                    egui::Grid::new(format!("{} relationships", character.name)).show(ui, |ui| {
+                        ui.label("Toward");
+                        ui.label("Affection");
+                        ui.label("Trust");
+                        ui.label("Respect");
+                        ui.end_row();
+                        for (other, relationship) in relationships.known_by(&character.name) {
+                            ui.label(other);
+                            ui.label(format!("{:.0}", relationship.affection));
+                            ui.label(format!("{:.0}", relationship.trust));
+                            ui.label(format!("{:.0}", relationship.respect));
+                            ui.end_row();
+                        }
+                    });
+                });
+            }
+        });
+}
+
+/// Explains why NPCs are silent when there is no API key or the endpoint refused it.
+fn api_key_system(mut contexts: EguiContexts, credentials: Option<Res<Credentials>>) {
+    let Some(credentials) = credentials else {
+        return;
+    };
+    let headline =
+        match &credentials.status {
+            KeyStatus::Missing => {
+                "No OpenAI API key was found, so the villagers have nothing to say.".to_string()
+            }
+            KeyStatus::Rejected(reason) => format!(
+            "The OpenAI API key from {} was rejected ({}), so the villagers have nothing to say.",
+            credentials.source.as_ref().map(ToString::to_string).unwrap_or_default(),
+            reason
+        ),
+            _ => return,
+        };
+    egui::Window::new("OpenAI API key")
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 20.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(headline);
+            for problem in &credentials.problems {
+                ui.label(problem);
+            }
+            ui.label(format!(
+                "Set {}, put the key in a file only you can read at ~/.config/bevy_rpg/api_key, \
+                 or store it encrypted with `cargo run --bin store_api_key` and set {}. \
+                 Then restart the game.",
+                credentials::KEY_VAR,
+                credentials::PASSPHRASE_VAR
+            ));
+        });
+}
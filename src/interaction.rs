@@ -0,0 +1,259 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    animation::Facing,
+    clock::{GameClock, TimeSkipped},
+    controls::{ActionState, Controls, InputAction},
+    death::Dead,
+    navigation::{NavigationGrid, CHARACTER_RADIUS},
+    Action, Character, Item, Plant, Player, NPC,
+};
+
+const TALK_RANGE: f32 = 120.0;
+const PICK_UP_RANGE: f32 = 50.0;
+const BED_RANGE: f32 = 80.0;
+const DOOR_RANGE: f32 = 80.0;
+/// Talking to an NPC makes them answer within this many seconds.
+const REPLY_DELAY: f32 = 2.0;
+const WAKE_UP_HOUR: f32 = 6.0;
+
+/// Something lying on the ground that can be picked up.
+#[derive(Component)]
+pub struct GroundItem {
+    pub item: Item,
+    pub count: u32,
+}
+
+/// Where the player can sleep through the night.
+#[derive(Component)]
+pub struct Bed;
+
+#[derive(Component)]
+pub struct Door {
+    /// Name of the building, e.g. "Theo's house".
+    pub building: String,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum InteractionKind {
+    Harvest,
+    Talk(String),
+    PickUp(String),
+    Sleep,
+    Knock(String),
+}
+
+impl InteractionKind {
+    pub fn label(&self) -> String {
+        match self {
+            InteractionKind::Harvest => "Harvest".to_string(),
+            InteractionKind::Talk(name) => format!("Talk to {}", name),
+            InteractionKind::PickUp(what) => format!("Pick up {}", what),
+            InteractionKind::Sleep => "Sleep until morning".to_string(),
+            InteractionKind::Knock(building) => format!("Knock on {}", building),
+        }
+    }
+}
+
+/// What the player would interact with if they pressed the interact key now.
+#[derive(Resource, Default)]
+pub struct InteractionTarget {
+    pub target: Option<(Entity, InteractionKind)>,
+}
+
+/// How well `target` lines up with where a character at `position` is facing, lower is better,
+/// or `None` when it is behind them or out of `range`.
+pub fn in_front(position: Vec2, facing: &Facing, target: Vec2, range: f32) -> Option<f32> {
+    let offset = target - position;
+    let distance = offset.length();
+    let alignment = facing.direction.dot(offset.normalize_or_zero());
+    let in_front = alignment >= 0.0 || distance < 1.0;
+    (in_front && distance < range).then_some(distance * (2.0 - alignment))
+}
+
+/// Picks the single best thing in front of the player to interact with.
+#[allow(clippy::too_many_arguments)]
+pub fn find_interaction_target(
+    clock: Res<GameClock>,
+    mut target: ResMut<InteractionTarget>,
+    player: Query<(&Transform, &Facing), (With<Player>, Without<Dead>)>,
+    plants: Query<(Entity, &Transform, &Plant)>,
+    npcs: Query<(Entity, &Transform, &Character), (With<NPC>, Without<Dead>)>,
+    ground_items: Query<(Entity, &Transform, &GroundItem)>,
+    beds: Query<(Entity, &Transform), With<Bed>>,
+    doors: Query<(Entity, &Transform, &Door)>,
+) {
+    target.target = None;
+    let Ok((player_transform, facing)) = player.get_single() else {
+        return;
+    };
+    let position = player_transform.translation.xy();
+    let hour = clock.now.hour();
+    let bedtime = !(WAKE_UP_HOUR..20.0).contains(&hour);
+
+    let candidates = plants
+        .iter()
+        .filter(|(_, _, plant)| plant.is_grown())
+        .map(|(entity, transform, _)| {
+            (
+                entity,
+                transform,
+                Plant::HARVEST_RANGE,
+                InteractionKind::Harvest,
+            )
+        })
+        .chain(npcs.iter().map(|(entity, transform, character)| {
+            (
+                entity,
+                transform,
+                TALK_RANGE,
+                InteractionKind::Talk(character.name.clone()),
+            )
+        }))
+        .chain(ground_items.iter().map(|(entity, transform, ground_item)| {
+            (
+                entity,
+                transform,
+                PICK_UP_RANGE,
+                InteractionKind::PickUp(format!("{} {}", ground_item.count, ground_item.item)),
+            )
+        }))
+        .chain(
+            beds.iter()
+                .filter(|_| bedtime)
+                .map(|(entity, transform)| (entity, transform, BED_RANGE, InteractionKind::Sleep)),
+        )
+        .chain(doors.iter().map(|(entity, transform, door)| {
+            (
+                entity,
+                transform,
+                DOOR_RANGE,
+                InteractionKind::Knock(door.building.clone()),
+            )
+        }));
+    target.target = candidates
+        .filter_map(|(entity, transform, range, kind)| {
+            in_front(position, facing, transform.translation.xy(), range)
+                .map(|score| (score, entity, kind))
+        })
+        .min_by(|(a, ..), (b, ..)| a.total_cmp(b))
+        .map(|(_, entity, kind)| (entity, kind));
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn interact(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    target: Res<InteractionTarget>,
+    mut clock: ResMut<GameClock>,
+    mut skipped: EventWriter<TimeSkipped>,
+    mut player: Query<&mut Character, (With<Player>, Without<Dead>)>,
+    mut npcs: Query<&mut NPC>,
+    ground_items: Query<&GroundItem>,
+) {
+    if !actions.just_pressed(InputAction::Interact) {
+        return;
+    }
+    let (Some((entity, kind)), Ok(mut character)) = (&target.target, player.get_single_mut())
+    else {
+        return;
+    };
+    match kind {
+        InteractionKind::Harvest => character.actions.push(Action::Harvest(Some(*entity))),
+        InteractionKind::Talk(name) => {
+            character
+                .actions
+                .push(Action::Talk(format!("Hello, {}!", name)));
+            if let Ok(mut npc) = npcs.get_mut(*entity) {
+                npc.chat_cooldown = npc.chat_cooldown.min(REPLY_DELAY);
+                npc.addressed = true;
+            }
+        }
+        InteractionKind::PickUp(_) => {
+            if let Ok(ground_item) = ground_items.get(*entity) {
+                character
+                    .items
+                    .push((ground_item.item.clone(), ground_item.count));
+                commands.entity(*entity).despawn_recursive();
+            }
+        }
+        InteractionKind::Sleep => {
+            let wake_up = clock.now.next_hour(WAKE_UP_HOUR);
+            println!("{} sleeps until {}", character.name, wake_up.timestamp());
+            skipped.send(TimeSkipped {
+                minutes: wake_up.0 - clock.now.0,
+            });
+            clock.now = wake_up;
+            character
+                .actions
+                .push(Action::Emote("sleeps through the night".to_string()));
+        }
+        InteractionKind::Knock(building) => {
+            character
+                .actions
+                .push(Action::Emote(format!("knocks on the door of {}", building)));
+        }
+    }
+}
+
+pub fn prompt_system(
+    mut contexts: EguiContexts,
+    controls: Res<Controls>,
+    target: Res<InteractionTarget>,
+) {
+    let Some((_, kind)) = &target.target else {
+        return;
+    };
+    egui::Area::new(egui::Id::new("interaction prompt"))
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -40.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(format!(
+                    "{}: {}",
+                    controls.describe(InputAction::Interact),
+                    kind.label()
+                ))
+                .heading()
+                .color(egui::Color32::WHITE)
+                .background_color(egui::Color32::from_black_alpha(160)),
+            );
+        });
+}
+
+/// Puts a door in front of every named building on the map.
+pub fn spawn_doors(commands: &mut Commands, grid: &NavigationGrid) {
+    for obstacle in &grid.obstacles {
+        let Some(building) = &obstacle.name else {
+            continue;
+        };
+        let rect = obstacle.rect();
+        let position = Vec2::new(rect.center().x, rect.min.y - CHARACTER_RADIUS - 5.0);
+        commands.spawn((
+            Door {
+                building: building.clone(),
+            },
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.45, 0.3, 0.15),
+                    custom_size: Some(Vec2::new(40.0, 10.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(0.0)),
+                ..default()
+            },
+        ));
+    }
+}
+
+pub fn ground_item_bundle(texture: Handle<Image>, position: Vec2) -> SpriteBundle {
+    SpriteBundle {
+        texture,
+        transform: Transform {
+            translation: position.extend(0.0),
+            scale: Vec3::new(0.3, 0.3, 0.0),
+            ..default()
+        },
+        ..default()
+    }
+}
@@ -0,0 +1,92 @@
+//! A medieval farming village whose villagers are driven by a language model, as Bevy plugins.
+//!
+//! Add the plugins to an app, then spawn `Region`s, `Plant`s and characters, or use
+//! `village::spawn_village` for the full game world.
+
+// Bevy queries and system parameters trip this lint by design
+#![allow(clippy::type_complexity)]
+
+use bevy::prelude::*;
+
+pub mod animation;
+pub mod audio;
+pub mod behaviour;
+pub mod character;
+pub mod clock;
+pub mod config;
+pub mod controls;
+pub mod credentials;
+pub mod death;
+pub mod dialog;
+pub mod experiment;
+pub mod farming;
+pub mod hud;
+pub mod interaction;
+pub mod mock_server;
+pub mod navigation;
+pub mod needs;
+pub mod npc;
+pub mod perception;
+pub mod plan;
+pub mod prompt;
+pub mod relationship;
+pub mod save;
+pub mod schedule;
+pub mod speech;
+pub mod storyline;
+pub mod think;
+pub mod village;
+
+pub use audio::{AudioFxPlugin, AudioFxSettings};
+pub use character::{
+    fill_character, Action, ActionPerformed, Character, CharacterPlugin, CharacterSettings, Item,
+    Memory, Player, StartPos,
+};
+pub use dialog::{DialogBackendPlugin, DialogSettings};
+pub use farming::{fill_rect_with_plants, FarmingPlugin, FarmingSettings, Plant, Region};
+pub use hud::{HudPlugin, HudSettings};
+pub use npc::{NPCState, NpcAiPlugin, NpcAiSettings, NPC};
+
+/// The order the plugins' gameplay systems run in each frame.
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GameSet {
+    /// The clock moves on.
+    Time,
+    /// Characters move, get hungry and think; plants grow.
+    Simulation,
+    /// The player's interact key turns into actions.
+    Interaction,
+    /// NPCs follow their schedules and needs.
+    Decisions,
+    /// Who sees and hears what.
+    Perception,
+    /// Observations become memories, relationships and storyline progress.
+    Memory,
+    /// Queued actions are carried out.
+    Actions,
+}
+
+/// Inserts the resource made by `load` unless the app already has one, so a host app or test
+/// can provide its own map, schedules and so on before adding the plugins.
+fn insert_if_missing<R: Resource>(app: &mut App, load: impl FnOnce() -> R) {
+    if !app.world.contains_resource::<R>() {
+        app.insert_resource(load());
+    }
+}
+
+/// Every plugin calls this, so any combination of them runs in the same order.
+fn configure_sets(app: &mut App) {
+    app.configure_sets(
+        Update,
+        (
+            GameSet::Time,
+            GameSet::Simulation,
+            GameSet::Interaction,
+            GameSet::Decisions,
+            GameSet::Perception,
+            GameSet::Memory,
+            GameSet::Actions,
+        )
+            .chain(),
+    );
+}
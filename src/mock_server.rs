@@ -0,0 +1,409 @@
+//! A stand-in for the OpenAI chat completions API, for developing and testing the dialog backend
+//! offline. Run it with `cargo run --bin mock_openai`.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::dialog::{
+    OpenAIChoice, OpenAIError, OpenAIErrorResponse, OpenAIFunctionCall, OpenAIMessage,
+    OpenAIRequest, OpenAIResponse, OpenAIToolCall,
+};
+
+/// How long a request that "times out" is held open before the connection is dropped.
+const HANG_DURATION: Duration = Duration::from_secs(600);
+
+/// One canned answer. In JSON: `{"say": "Hello"}`,
+/// `{"tool_call": {"name": "set_task", "arguments": {"task": "farming"}}}`,
+/// `{"error": {"status": 429, "message": "Slow down", "type": "rate_limit_exceeded"}}`,
+/// `{"malformed": "<html>Bad gateway</html>"}` or `"timeout"`.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MockReply {
+    Say(String),
+    ToolCall {
+        name: String,
+        arguments: serde_json::Value,
+    },
+    Error {
+        status: u16,
+        message: String,
+        #[serde(rename = "type")]
+        error_type: String,
+    },
+    /// Answers 200 OK with this body as it is, such as a proxy's error page or `{"choices": []}`.
+    Malformed(String),
+    /// Never answers.
+    Timeout,
+}
+
+impl MockReply {
+    fn server_error() -> Self {
+        MockReply::Error {
+            status: 500,
+            message: "The server had an error while processing your request.".to_string(),
+            error_type: "server_error".to_string(),
+        }
+    }
+}
+
+/// Answers with `reply` when the prompt contains every one of `contains`, ignoring case.
+#[derive(Deserialize, Clone, Debug)]
+pub struct MockRule {
+    pub contains: Vec<String>,
+    pub reply: MockReply,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MockMode {
+    /// The replies in order, starting over after the last one.
+    #[default]
+    Scripted,
+    /// A reply picked at random, repeatable with the same seed.
+    Random,
+    /// The first rule that matches the prompt, or the next scripted reply.
+    Rules,
+}
+
+impl MockMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "scripted" => Some(MockMode::Scripted),
+            "random" => Some(MockMode::Random),
+            "rules" => Some(MockMode::Rules),
+            _ => None,
+        }
+    }
+}
+
+/// Replies and rules loaded from a file such as `assets/mock_openai/village.json`.
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct MockScript {
+    #[serde(default)]
+    pub replies: Vec<MockReply>,
+    #[serde(default)]
+    pub rules: Vec<MockRule>,
+}
+
+impl MockScript {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&source).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub mode: MockMode,
+    pub script: MockScript,
+    pub seed: u64,
+    /// Added to every answer to imitate a slow model.
+    pub delay: Duration,
+    /// Chance of answering with a server error instead.
+    pub error_rate: f32,
+    /// Chance of never answering.
+    pub timeout_rate: f32,
+    /// Key that requests must carry, like the real API, or any key when `None`.
+    pub api_key: Option<String>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            mode: MockMode::default(),
+            script: MockScript::default(),
+            seed: 0,
+            delay: Duration::ZERO,
+            error_rate: 0.0,
+            timeout_rate: 0.0,
+            api_key: None,
+        }
+    }
+}
+
+/// Villager-sounding answers for when no script is given.
+fn default_replies() -> Vec<MockReply> {
+    let set_task = |arguments: serde_json::Value| MockReply::ToolCall {
+        name: "set_task".to_string(),
+        arguments,
+    };
+    vec![
+        MockReply::Say("*nods* Good day to you.".to_string()),
+        set_task(serde_json::json!({"task": "farming"})),
+        MockReply::Say("*wipes brow* These fields won't tend themselves.".to_string()),
+        MockReply::Say("I could do with something to eat.".to_string()),
+        set_task(serde_json::json!({"task": "traveling", "destination": "Bill's Farm"})),
+        set_task(serde_json::json!({"task": "idle"})),
+    ]
+}
+
+/// Picks replies for requests according to a `MockConfig`.
+pub struct MockResponder {
+    config: MockConfig,
+    rng: StdRng,
+    next_reply: usize,
+    next_call_id: u32,
+}
+
+impl MockResponder {
+    pub fn new(mut config: MockConfig) -> Self {
+        if config.script.replies.is_empty() {
+            config.script.replies = default_replies();
+        }
+        MockResponder {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            next_reply: 0,
+            next_call_id: 0,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.config.delay
+    }
+
+    /// Whether a request with `authorization`, the value of its Authorization header, may pass.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        match &self.config.api_key {
+            Some(key) => {
+                authorization.and_then(|value| value.strip_prefix("Bearer ")) == Some(key.as_str())
+            }
+            None => true,
+        }
+    }
+
+    pub fn reply_to(&mut self, request: &OpenAIRequest) -> MockReply {
+        if self.rng.gen::<f32>() < self.config.timeout_rate {
+            return MockReply::Timeout;
+        }
+        if self.rng.gen::<f32>() < self.config.error_rate {
+            return MockReply::server_error();
+        }
+        let replies = &self.config.script.replies;
+        match self.config.mode {
+            MockMode::Random => replies[self.rng.gen_range(0..replies.len())].clone(),
+            MockMode::Rules => {
+                let prompt = request
+                    .messages
+                    .iter()
+                    .filter_map(|message| message.content.as_deref())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                    .to_lowercase();
+                let rule = self.config.script.rules.iter().find(|rule| {
+                    rule.contains
+                        .iter()
+                        .all(|needle| prompt.contains(&needle.to_lowercase()))
+                });
+                match rule {
+                    Some(rule) => rule.reply.clone(),
+                    None => self.next_scripted(),
+                }
+            }
+            MockMode::Scripted => self.next_scripted(),
+        }
+    }
+
+    fn next_scripted(&mut self) -> MockReply {
+        let replies = &self.config.script.replies;
+        let reply = replies[self.next_reply % replies.len()].clone();
+        self.next_reply += 1;
+        reply
+    }
+
+    /// The HTTP status and body for `reply`, or `None` for a timeout.
+    pub fn respond(&mut self, request: &OpenAIRequest, reply: MockReply) -> Option<(u16, String)> {
+        let message = match reply {
+            MockReply::Timeout => return None,
+            MockReply::Malformed(body) => return Some((200, body)),
+            MockReply::Error {
+                status,
+                message,
+                error_type,
+            } => return Some(error_response(status, message, error_type)),
+            MockReply::Say(speech) => {
+                let wants_json = request
+                    .response_format
+                    .as_ref()
+                    .is_some_and(|format| format["type"].as_str() == Some("json_object"));
+                let content = if wants_json {
+                    serde_json::json!({ "speech": speech }).to_string()
+                } else {
+                    speech
+                };
+                OpenAIMessage {
+                    role: "assistant".to_string(),
+                    content: Some(content),
+                    name: None,
+                    tool_calls: None,
+                }
+            }
+            MockReply::ToolCall { name, arguments } => {
+                self.next_call_id += 1;
+                OpenAIMessage {
+                    role: "assistant".to_string(),
+                    content: None,
+                    name: None,
+                    tool_calls: Some(vec![OpenAIToolCall {
+                        id: format!("call_{}", self.next_call_id),
+                        tool_type: "function".to_string(),
+                        function: OpenAIFunctionCall {
+                            name,
+                            arguments: arguments.to_string(),
+                        },
+                    }]),
+                }
+            }
+        };
+        let body = OpenAIResponse {
+            choices: vec![OpenAIChoice { message }],
+        };
+        Some((200, serde_json::to_string(&body).unwrap()))
+    }
+}
+
+/// Serves `POST /v1/chat/completions` and `GET /v1/models` on `listener` until the process exits, one thread per
+/// connection.
+pub fn serve(listener: TcpListener, config: MockConfig) {
+    let responder = Arc::new(Mutex::new(MockResponder::new(config)));
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let responder = responder.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &responder) {
+                println!("Mock connection failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Starts `serve` on a background thread, returning the endpoint URL to point the game at.
+pub fn spawn(config: MockConfig) -> io::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let endpoint = format!("http://{}/v1/chat/completions", listener.local_addr()?);
+    thread::spawn(move || serve(listener, config));
+    Ok(endpoint)
+}
+
+fn handle_connection(mut stream: TcpStream, responder: &Mutex<MockResponder>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    if !responder
+        .lock()
+        .unwrap()
+        .authorized(authorization.as_deref())
+    {
+        let (status, body) = error_response(
+            401,
+            "Incorrect API key provided.".to_string(),
+            "invalid_request_error".to_string(),
+        );
+        return write_response(&mut stream, status, &body);
+    }
+    if method == "GET" && path.ends_with("/models") {
+        let body = serde_json::json!({
+            "object": "list",
+            "data": [{"id": "mock", "object": "model", "owned_by": "mock_openai"}],
+        });
+        return write_response(&mut stream, 200, &body.to_string());
+    }
+    if method != "POST" || !path.ends_with("/chat/completions") {
+        let (status, body) = error_response(
+            404,
+            format!("Unknown request {} {}", method, path),
+            "invalid_request_error".to_string(),
+        );
+        return write_response(&mut stream, status, &body);
+    }
+
+    let request = match serde_json::from_slice::<OpenAIRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let (status, body) = error_response(
+                400,
+                format!("Could not parse request: {}", e),
+                "invalid_request_error".to_string(),
+            );
+            return write_response(&mut stream, status, &body);
+        }
+    };
+    let (delay, response) = {
+        let mut responder = responder.lock().unwrap();
+        let reply = responder.reply_to(&request);
+        println!("Mock reply: {:?}", reply);
+        (responder.delay(), responder.respond(&request, reply))
+    };
+    thread::sleep(delay);
+    match response {
+        Some((status, body)) => write_response(&mut stream, status, &body),
+        None => {
+            thread::sleep(HANG_DURATION);
+            Ok(())
+        }
+    }
+}
+
+fn error_response(status: u16, message: String, error_type: String) -> (u16, String) {
+    let body = OpenAIErrorResponse {
+        error: OpenAIError {
+            message,
+            error_type,
+        },
+    };
+    (status, serde_json::to_string(&body).unwrap())
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        _ => "Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,415 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fs,
+};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+pub const NAVIGATION_PATH: &str = "assets/navigation.json";
+
+const CELL_SIZE: f32 = 40.0;
+/// Characters collide with the world and each other as circles of this radius.
+pub const CHARACTER_RADIUS: f32 = 15.0;
+/// How far a target may be inside an obstacle and still be approached from the nearest free cell.
+const MAX_GOAL_SEARCH_CELLS: i32 = 10;
+const WAYPOINT_REACHED: f32 = 8.0;
+const MAX_CACHED_PATHS: usize = 512;
+/// Characters closer than this push each other apart while walking.
+const AVOID_RADIUS: f32 = 40.0;
+const AVOID_WEIGHT: f32 = 0.8;
+/// How far a character may walk without getting any closer to their next waypoint, such as
+/// when someone stands in a doorway, before the way counts as blocked.
+const STALL_DISTANCE: f32 = 300.0;
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ObstacleKind {
+    Building,
+    Water,
+    Fence,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Obstacle {
+    pub kind: ObstacleKind,
+    /// Buildings with a name, e.g. "Theo's house", get a door the player can knock on.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `[min_x, min_y, max_x, max_y]` in world coordinates.
+    pub rect: [f32; 4],
+}
+
+impl Obstacle {
+    pub fn rect(&self) -> Rect {
+        let [min_x, min_y, max_x, max_y] = self.rect;
+        Rect::new(min_x, min_y, max_x, max_y)
+    }
+}
+
+#[derive(Deserialize)]
+struct MapDefinition {
+    bounds: [f32; 4],
+    obstacles: Vec<Obstacle>,
+}
+
+/// Walkability of the map in square cells, with A* paths between them.
+#[derive(Resource)]
+pub struct NavigationGrid {
+    pub bounds: Rect,
+    pub obstacles: Vec<Obstacle>,
+    size: IVec2,
+    walkable: Vec<bool>,
+    /// Paths between cells, `None` when there is no way through.
+    cache: HashMap<(IVec2, IVec2), Option<Vec<IVec2>>>,
+}
+
+impl NavigationGrid {
+    /// Loads the map bounds and obstacles authored in `assets/navigation.json`.
+    pub fn load() -> Self {
+        let definition = fs::read_to_string(NAVIGATION_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                serde_json::from_str::<MapDefinition>(&source).map_err(|e| e.to_string())
+            });
+        let definition = definition.unwrap_or_else(|e| {
+            println!("Could not load {}: {}", NAVIGATION_PATH, e);
+            MapDefinition {
+                bounds: [-5000.0, -5000.0, 5000.0, 5000.0],
+                obstacles: vec![],
+            }
+        });
+        let [min_x, min_y, max_x, max_y] = definition.bounds;
+        let grid = Self::new(Rect::new(min_x, min_y, max_x, max_y), definition.obstacles);
+        let count = |kind| {
+            grid.obstacles
+                .iter()
+                .filter(|obstacle| obstacle.kind == kind)
+                .count()
+        };
+        println!(
+            "Navigation grid of {}x{} cells (buildings: {}, water: {}, fences: {})",
+            grid.size.x,
+            grid.size.y,
+            count(ObstacleKind::Building),
+            count(ObstacleKind::Water),
+            count(ObstacleKind::Fence)
+        );
+        grid
+    }
+
+    pub fn new(bounds: Rect, obstacles: Vec<Obstacle>) -> Self {
+        let size = (bounds.size() / CELL_SIZE).ceil().as_ivec2();
+        let mut grid = NavigationGrid {
+            bounds,
+            obstacles,
+            size,
+            walkable: vec![true; (size.x * size.y) as usize],
+            cache: HashMap::new(),
+        };
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let cell = IVec2::new(x, y);
+                let cell_rect = Rect::from_center_size(grid.center(cell), Vec2::splat(CELL_SIZE));
+                let blocked = grid.obstacles.iter().any(|obstacle| {
+                    let grown = obstacle.rect().inset(CHARACTER_RADIUS);
+                    !grown.intersect(cell_rect).is_empty()
+                });
+                grid.walkable[(y * size.x + x) as usize] = !blocked;
+            }
+        }
+        grid
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        ((position - self.bounds.min) / CELL_SIZE)
+            .floor()
+            .as_ivec2()
+    }
+
+    fn center(&self, cell: IVec2) -> Vec2 {
+        self.bounds.min + (cell.as_vec2() + 0.5) * CELL_SIZE
+    }
+
+    fn is_walkable_cell(&self, cell: IVec2) -> bool {
+        cell.cmpge(IVec2::ZERO).all()
+            && cell.cmplt(self.size).all()
+            && self.walkable[(cell.y * self.size.x + cell.x) as usize]
+    }
+
+    pub fn is_walkable(&self, position: Vec2) -> bool {
+        self.is_walkable_cell(self.cell(position))
+    }
+
+    /// The free cell closest to `cell`, for targets inside obstacles or off the map.
+    fn nearest_walkable(&self, cell: IVec2) -> Option<IVec2> {
+        (0..=MAX_GOAL_SEARCH_CELLS).find_map(|radius| {
+            (-radius..=radius)
+                .flat_map(|x| (-radius..=radius).map(move |y| IVec2::new(x, y)))
+                .filter(|offset| offset.x.abs() == radius || offset.y.abs() == radius)
+                .map(|offset| cell + offset)
+                .filter(|&candidate| self.is_walkable_cell(candidate))
+                .min_by_key(|candidate| (*candidate - cell).length_squared())
+        })
+    }
+
+    fn neighbours(&self, cell: IVec2) -> impl Iterator<Item = (IVec2, u32)> + '_ {
+        [
+            (IVec2::X, STRAIGHT_COST),
+            (IVec2::NEG_X, STRAIGHT_COST),
+            (IVec2::Y, STRAIGHT_COST),
+            (IVec2::NEG_Y, STRAIGHT_COST),
+            (IVec2::new(1, 1), DIAGONAL_COST),
+            (IVec2::new(1, -1), DIAGONAL_COST),
+            (IVec2::new(-1, 1), DIAGONAL_COST),
+            (IVec2::new(-1, -1), DIAGONAL_COST),
+        ]
+        .into_iter()
+        .filter(move |(offset, _)| {
+            // diagonal steps may not cut the corner of an obstacle
+            self.is_walkable_cell(cell + *offset)
+                && self.is_walkable_cell(cell + IVec2::new(offset.x, 0))
+                && self.is_walkable_cell(cell + IVec2::new(0, offset.y))
+        })
+        .map(move |(offset, cost)| (cell + offset, cost))
+    }
+
+    fn heuristic(from: IVec2, to: IVec2) -> u32 {
+        let delta = (to - from).abs();
+        let diagonal = delta.x.min(delta.y) as u32;
+        let straight = delta.x.max(delta.y) as u32 - diagonal;
+        diagonal * DIAGONAL_COST + straight * STRAIGHT_COST
+    }
+
+    fn a_star(&self, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+        let mut open = BinaryHeap::new();
+        let mut came_from = HashMap::new();
+        let mut costs = HashMap::from([(start, 0)]);
+        open.push(Reverse((Self::heuristic(start, goal), start.x, start.y)));
+        while let Some(Reverse((_, x, y))) = open.pop() {
+            let cell = IVec2::new(x, y);
+            if cell == goal {
+                let mut path = vec![goal];
+                while let Some(&previous) = came_from.get(path.last().unwrap()) {
+                    path.push(previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let cost = costs[&cell];
+            for (next, step_cost) in self.neighbours(cell) {
+                let next_cost = cost + step_cost;
+                if costs.get(&next).is_none_or(|&known| next_cost < known) {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((
+                        next_cost + Self::heuristic(next, goal),
+                        next.x,
+                        next.y,
+                    )));
+                }
+            }
+        }
+        None
+    }
+
+    fn line_is_walkable(&self, from: Vec2, to: Vec2) -> bool {
+        let steps = (from.distance(to) / (CELL_SIZE / 4.0)).ceil().max(1.0) as u32;
+        (0..=steps).all(|step| self.is_walkable(from.lerp(to, step as f32 / steps as f32)))
+    }
+
+    /// Whether a character standing at `position` would overlap an obstacle or stick out of the map.
+    pub fn collides(&self, position: Vec2) -> bool {
+        !self.bounds.inset(-CHARACTER_RADIUS).contains(position)
+            || self
+                .obstacles
+                .iter()
+                .any(|obstacle| obstacle.rect().inset(CHARACTER_RADIUS).contains(position))
+    }
+
+    /// Whether nothing tall enough to hide behind stands between `from` and `to`. Only buildings
+    /// block the view; water and fences can be seen over.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let delta = to - from;
+        !self
+            .obstacles
+            .iter()
+            .filter(|obstacle| obstacle.kind == ObstacleKind::Building)
+            .any(|obstacle| {
+                // slab test of the segment against the building's rectangle
+                let rect = obstacle.rect();
+                let mut entry: f32 = 0.0;
+                let mut exit: f32 = 1.0;
+                for axis in 0..2 {
+                    if delta[axis].abs() < f32::EPSILON {
+                        if from[axis] < rect.min[axis] || from[axis] > rect.max[axis] {
+                            return false;
+                        }
+                        continue;
+                    }
+                    let a = (rect.min[axis] - from[axis]) / delta[axis];
+                    let b = (rect.max[axis] - from[axis]) / delta[axis];
+                    entry = entry.max(a.min(b));
+                    exit = exit.min(a.max(b));
+                }
+                entry <= exit
+            })
+    }
+
+    /// Moves a character from `from` by up to `delta`, sliding along walls and the edge of the map
+    /// instead of stopping dead. Characters at `others` can't be walked into, though anyone already
+    /// overlapping them is free to move apart.
+    pub fn slide(&self, from: Vec2, delta: Vec2, others: &[Vec2]) -> Vec2 {
+        let allowed = |to: Vec2| {
+            !self.collides(to)
+                && others.iter().all(|other| {
+                    let distance = other.distance(to);
+                    distance >= CHARACTER_RADIUS * 2.0 || distance >= other.distance(from)
+                })
+        };
+        [delta, Vec2::new(delta.x, 0.0), Vec2::new(0.0, delta.y)]
+            .into_iter()
+            .map(|delta| from + delta)
+            .find(|&to| allowed(to))
+            .unwrap_or(from)
+    }
+
+    /// Waypoints from `from` to `to`, ending at `to` itself when it can be stood on.
+    pub fn find_path(&mut self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let start = self.nearest_walkable(self.cell(from))?;
+        let goal = self.nearest_walkable(self.cell(to))?;
+        if self.cache.len() > MAX_CACHED_PATHS {
+            self.cache.clear();
+        }
+        let cells = match self.cache.get(&(start, goal)) {
+            Some(cells) => cells.clone(),
+            None => {
+                let cells = self.a_star(start, goal);
+                self.cache.insert((start, goal), cells.clone());
+                cells
+            }
+        }?;
+
+        let mut points = cells
+            .into_iter()
+            .map(|cell| self.center(cell))
+            .collect::<Vec<_>>();
+        if self.is_walkable(to) {
+            *points.last_mut().unwrap() = to;
+        }
+        // skip waypoints that can be walked past in a straight line
+        let mut waypoints = vec![];
+        let mut current = from;
+        let mut index = 0;
+        while index < points.len() {
+            let mut furthest = index;
+            while furthest + 1 < points.len()
+                && self.line_is_walkable(current, points[furthest + 1])
+            {
+                furthest += 1;
+            }
+            current = points[furthest];
+            waypoints.push(current);
+            index = furthest + 1;
+        }
+        Some(waypoints)
+    }
+}
+
+/// The route a character is currently following.
+#[derive(Default)]
+pub struct NavPath {
+    goal: Option<Vec2>,
+    waypoints: Vec<Vec2>,
+    unreachable: bool,
+    /// Closest the character has come to the next waypoint, `None` until they set out for it.
+    closest: Option<f32>,
+    /// How far the character has walked since last getting closer to the next waypoint.
+    stalled: f32,
+}
+
+pub enum Step {
+    Moving,
+    Arrived,
+    /// There is no way to the target, or the way has been blocked for too long.
+    Unreachable,
+}
+
+impl NavPath {
+    /// Moves `position` up to `distance` along a path to `target`, replanning when the target moves
+    /// to another cell and keeping clear of the characters at `others`. Gives up when the
+    /// characters stop it from getting any closer for a while.
+    pub fn step_towards(
+        &mut self,
+        grid: &mut NavigationGrid,
+        position: &mut Vec3,
+        target: Vec2,
+        distance: f32,
+        others: &[Vec2],
+    ) -> Step {
+        let replan = self
+            .goal
+            .is_none_or(|goal| grid.cell(goal) != grid.cell(target));
+        if replan {
+            self.goal = Some(target);
+            self.closest = None;
+            self.stalled = 0.0;
+            match grid.find_path(position.xy(), target) {
+                Some(waypoints) => {
+                    self.waypoints = waypoints;
+                    self.unreachable = false;
+                }
+                None => {
+                    self.waypoints.clear();
+                    self.unreachable = true;
+                }
+            }
+        }
+        if self.unreachable {
+            return Step::Unreachable;
+        }
+
+        while self
+            .waypoints
+            .first()
+            .is_some_and(|waypoint| waypoint.distance(position.xy()) < WAYPOINT_REACHED)
+        {
+            self.waypoints.remove(0);
+            self.closest = None;
+        }
+        let Some(&waypoint) = self.waypoints.first() else {
+            return Step::Arrived;
+        };
+
+        let remaining = waypoint.distance(position.xy());
+        if self.closest.is_none_or(|closest| remaining < closest - 1.0) {
+            self.closest = Some(remaining);
+            self.stalled = 0.0;
+        } else {
+            self.stalled += distance;
+            if self.stalled > STALL_DISTANCE {
+                // start afresh next time, the way may have cleared by then
+                *self = NavPath::default();
+                return Step::Unreachable;
+            }
+        }
+
+        let heading = (waypoint - position.xy()).normalize_or_zero();
+        let avoidance = others
+            .iter()
+            .map(|other| position.xy() - *other)
+            .filter(|away| away.length() > 0.01 && away.length() < AVOID_RADIUS)
+            .map(|away| away.normalize() * (1.0 - away.length() / AVOID_RADIUS))
+            .sum::<Vec2>();
+        let steered = (heading + avoidance * AVOID_WEIGHT).normalize_or_zero();
+        let step = distance.min(waypoint.distance(position.xy()));
+        let next = grid.slide(position.xy(), steered * step, others);
+        position.x = next.x;
+        position.y = next.y;
+        Step::Moving
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use itertools::Itertools;
+
+use crate::{
+    clock::GameClock,
+    death::Dead,
+    plan::{Plan, PlanStep},
+    relationship::Relationships,
+    Action, Character, NPCState, Plant, Region, NPC,
+};
+
+/// Saturation below which a character is hungry and eats whatever they carry.
+pub const HUNGRY_SATURATION: f32 = 30.0;
+pub const STARVING_SATURATION: f32 = 15.0;
+
+/// How long a hungry NPC sticks with a way of getting food before reconsidering, in seconds.
+const FOOD_DECISION_COOLDOWN: f32 = 10.0;
+/// How close someone has to be to be asked for food, or to be heard asking.
+const ASK_RANGE: f32 = 300.0;
+/// Sharing only happens out of surplus, so nobody gives away their last meal.
+const MIN_FOOD_TO_SHARE: u32 = 2;
+/// Game minutes of farming a hungry NPC offers in return for food.
+const TRADE_MINUTES: f32 = 60.0;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Hunger {
+    Fed,
+    Hungry,
+    Starving,
+}
+
+impl Hunger {
+    pub fn of(saturation: f32) -> Self {
+        if saturation < STARVING_SATURATION {
+            Hunger::Starving
+        } else if saturation < HUNGRY_SATURATION {
+            Hunger::Hungry
+        } else {
+            Hunger::Fed
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Hunger::Fed => "fed",
+            Hunger::Hungry => "hungry",
+            Hunger::Starving => "starving",
+        }
+    }
+}
+
+pub fn food_count(character: &Character) -> u32 {
+    character
+        .items
+        .iter()
+        .filter(|(item, _)| item.saturation() > 0.0)
+        .map(|(_, count)| count)
+        .sum()
+}
+
+/// Ripe plants per region, most first.
+fn ripe_plants_by_region<'a>(
+    plants: impl Iterator<Item = (&'a Transform, &'a Plant)>,
+    regions: &[&'a Region],
+) -> Vec<(&'a Region, usize)> {
+    let mut counts = HashMap::new();
+    for (plant_transform, plant) in plants {
+        if !plant.is_grown() {
+            continue;
+        }
+        for (index, region) in regions.iter().enumerate() {
+            if region.range.contains(plant_transform.translation.xy()) {
+                *counts.entry(index).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(index, count)| (regions[index], count))
+        .sorted_by_key(|(region, count)| (std::cmp::Reverse(*count), region.name.clone()))
+        .collect()
+}
+
+/// Prompt fragments telling a character where food can be found, e.g. "at Bill's Farm (12 ripe plants)".
+pub fn describe_food_sources<'a>(
+    name: &str,
+    position: Vec2,
+    characters: impl Iterator<Item = (&'a Character, &'a Transform)>,
+    plants: impl Iterator<Item = (&'a Transform, &'a Plant)>,
+    regions: impl Iterator<Item = &'a Region>,
+) -> Vec<String> {
+    let regions = regions.collect::<Vec<_>>();
+    let mut sources = ripe_plants_by_region(plants, &regions)
+        .into_iter()
+        .take(3)
+        .map(|(region, count)| format!("at {} ({} ripe plants)", region.name, count))
+        .collect::<Vec<_>>();
+    for (character, transform) in characters {
+        let food = food_count(character);
+        if character.name != name
+            && food > 0
+            && transform.translation.xy().distance(position) < ASK_RANGE
+        {
+            sources.push(format!(
+                "with {} (who carries {} food)",
+                character.name, food
+            ));
+        }
+    }
+    sources
+}
+
+/// Utility layer for hungry NPCs with nothing to eat: farm where they are, ask someone nearby
+/// who has food, or travel to the field with the most ripe plants, in that order of preference.
+/// Someone who was asked and didn't give is next offered a trade, an hour's work on their fields
+/// in return for food.
+pub fn seek_food(
+    time: Res<Time>,
+    mut asked: Local<HashMap<Entity, String>>,
+    mut npcs: Query<(Entity, &mut NPC, &mut Character, &Transform), Without<Dead>>,
+    others: Query<(&Character, &Transform), (Without<NPC>, Without<Dead>)>,
+    plants: Query<(&Transform, &Plant)>,
+    regions: Query<&Region>,
+) {
+    let regions = regions.iter().collect::<Vec<_>>();
+    let ripe_regions = ripe_plants_by_region(plants.iter(), &regions);
+    let food_holders = npcs
+        .iter()
+        .map(|(_, _, character, transform)| (character, transform))
+        .chain(others.iter())
+        .filter(|(character, _)| food_count(character) >= MIN_FOOD_TO_SHARE)
+        .map(|(character, transform)| (character.name.clone(), transform.translation.xy()))
+        .collect::<Vec<_>>();
+
+    for (entity, mut npc, mut character, transform) in &mut npcs {
+        npc.food_decision_cooldown -= time.delta_seconds();
+        let hunger = Hunger::of(character.saturation);
+        if hunger == Hunger::Fed || food_count(&character) > 0 {
+            asked.remove(&entity);
+            continue;
+        }
+        if npc.food_decision_cooldown > 0.0 || matches!(npc.state, NPCState::Sleeping) {
+            continue;
+        }
+        npc.food_decision_cooldown = FOOD_DECISION_COOLDOWN;
+
+        let position = transform.translation.xy();
+        let current_region = ripe_regions
+            .iter()
+            .find(|(region, _)| region.range.contains(position));
+        if current_region.is_some() {
+            if !matches!(npc.state, NPCState::Farming) {
+                println!(
+                    "{} is {} and starts farming",
+                    character.name,
+                    hunger.as_str()
+                );
+                npc.state = NPCState::Farming;
+            }
+            continue;
+        }
+
+        let nearby_holder = food_holders
+            .iter()
+            .filter(|(name, holder_position)| {
+                *name != character.name && holder_position.distance(position) < ASK_RANGE
+            })
+            .min_by(|(_, a), (_, b)| a.distance(position).total_cmp(&b.distance(position)));
+        if let Some((holder, _)) = nearby_holder {
+            println!(
+                "{} is {} and asks {} for food",
+                character.name,
+                hunger.as_str(),
+                holder
+            );
+            let plea = match hunger {
+                _ if asked.get(&entity) == Some(holder) => {
+                    println!("{} offers {} a trade", character.name, holder);
+                    format!(
+                        "{}, I'll work your fields for an hour in return for some food.",
+                        holder
+                    )
+                }
+                Hunger::Starving => {
+                    format!("{}, please, I'm starving. Can you spare some food?", holder)
+                }
+                _ => format!("{}, could you spare some food? I'm hungry.", holder),
+            };
+            asked.insert(entity, holder.clone());
+            character.actions.push(Action::Talk(plea));
+            continue;
+        }
+
+        // a traveling NPC someone already sent somewhere useful keeps going
+        let already_heading_to_food = matches!(&npc.state, NPCState::Traveling(destination)
+            if ripe_regions.iter().any(|(region, _)| region.name == *destination));
+        if let Some((region, _)) = ripe_regions.first() {
+            if !already_heading_to_food {
+                println!(
+                    "{} is {} and heads to {} for food",
+                    character.name,
+                    hunger.as_str(),
+                    region.name
+                );
+                npc.state = NPCState::Traveling(region.name.clone());
+            }
+        }
+    }
+}
+
+/// Whether `speech` offers something in return for the food it asks for.
+fn offers_trade(speech: &str) -> bool {
+    speech.to_lowercase().contains("in return")
+}
+
+/// NPCs with food to spare give some to people nearby who ask them for it by name,
+/// unless they dislike them. A trade is taken even from someone they dislike, and an NPC who
+/// traded for food then owes an hour's work on the giver's home fields.
+pub fn share_food(
+    clock: Res<GameClock>,
+    mut npcs: Query<(&mut NPC, &mut Character, &Transform), Without<Dead>>,
+    askers: Query<(&Character, &Transform), (Without<NPC>, Without<Dead>)>,
+    relationships: Res<Relationships>,
+) {
+    let requests = npcs
+        .iter()
+        .map(|(_, character, transform)| (character, transform))
+        .chain(askers.iter())
+        .flat_map(|(character, transform)| {
+            character
+                .actions
+                .iter()
+                .filter_map(move |action| match action {
+                    Action::Talk(speech) if speech.to_lowercase().contains("food") => Some((
+                        character.name.clone(),
+                        transform.translation.xy(),
+                        speech.clone(),
+                    )),
+                    _ => None,
+                })
+        })
+        .collect::<Vec<_>>();
+
+    let mut trades = vec![];
+    for (asker, asker_position, speech) in requests {
+        let trade = offers_trade(&speech);
+        for (npc, mut character, transform) in &mut npcs {
+            let addressed = speech.contains(&character.name);
+            let in_range = transform.translation.xy().distance(asker_position) < ASK_RANGE;
+            let willing = trade || relationships.get(&character.name, &asker).affection >= 0.0;
+            if character.name != asker
+                && addressed
+                && in_range
+                && willing
+                && food_count(&character) >= MIN_FOOD_TO_SHARE
+            {
+                println!("{} gives food to {}", character.name, asker);
+                character.actions.push(Action::Give(asker.clone()));
+                if trade {
+                    trades.push((asker.clone(), character.name.clone(), npc.home.clone()));
+                }
+            }
+        }
+    }
+
+    for (asker, giver, home) in trades {
+        let Some((mut npc, _, _)) = npcs
+            .iter_mut()
+            .find(|(_, character, _)| character.name == asker)
+        else {
+            continue;
+        };
+        println!("{} goes to work off the food {} traded", asker, giver);
+        let mut steps = vec![];
+        if !home.is_empty() {
+            steps.push(PlanStep::Travel { destination: home });
+        }
+        steps.push(PlanStep::Farm {
+            minutes: TRADE_MINUTES,
+        });
+        npc.plan = Some(Plan {
+            goal: format!("work off the food {} traded you", giver),
+            steps,
+            current: 0,
+            step_started: None,
+            talked: false,
+        });
+        npc.remember(
+            clock.now,
+            asker.clone(),
+            Action::Event(format!(
+                "You promised {} an hour's work on their fields in return for food.",
+                giver
+            )),
+        );
+    }
+}
+
+/// Tints characters so hunger can be seen at a glance.
+pub fn show_hunger(mut characters: Query<(&Character, &mut Sprite), Without<Dead>>) {
+    for (character, mut sprite) in &mut characters {
+        sprite.color = match Hunger::of(character.saturation) {
+            Hunger::Fed => Color::WHITE,
+            Hunger::Hungry => Color::rgb(1.0, 0.92, 0.8),
+            Hunger::Starving => Color::rgb(0.75, 0.72, 0.68),
+        };
+    }
+}
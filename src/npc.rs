@@ -0,0 +1,647 @@
+use bevy::prelude::*;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    behaviour,
+    clock::{self, GameClock},
+    death,
+    dialog::{DialogCancel, DialogPrompt, DialogReply, DialogSettings, ModelRole, OpenAIMessage},
+    experiment, navigation, needs, perception, plan, prompt, relationship, schedule, speech,
+    storyline,
+    think::{self, Staleness, ThinkPriority, ThinkScheduler, ThoughtContext},
+    Action, Character, GameSet, Memory, Plant, Player, Region,
+};
+
+/// Tuning for how often NPCs think.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct NpcAiSettings {
+    /// Seconds between an NPC's requests to the model.
+    pub chat_cooldown: f32,
+    /// Most requests to the model in flight at once, the rest wait their turn.
+    pub max_concurrent_requests: usize,
+    /// Seconds a prompt may wait for its turn before it is too old to send, and a request may go
+    /// unanswered before it is cancelled.
+    pub stale_after: f32,
+    /// NPCs within this distance of the player think before those further away.
+    pub near_player_distance: f32,
+    /// Memories an NPC keeps before the oldest are condensed into a summary.
+    pub summarize_after: usize,
+    /// Most recent memories left out of a summary.
+    pub keep_recent: usize,
+}
+
+impl Default for NpcAiSettings {
+    fn default() -> Self {
+        NpcAiSettings {
+            chat_cooldown: NPC::CHAT_COOLDOWN,
+            max_concurrent_requests: 2,
+            stale_after: 20.0,
+            near_player_distance: 300.0,
+            summarize_after: 40,
+            keep_recent: 12,
+        }
+    }
+}
+
+/// Everything that makes NPCs act on their own: schedules, needs, perception, memory,
+/// relationships, storylines and the prompts sent to the dialog backend.
+pub struct NpcAiPlugin;
+
+impl Plugin for NpcAiPlugin {
+    fn build(&self, app: &mut App) {
+        crate::configure_sets(app);
+        crate::insert_if_missing(app, experiment::Experiment::from_env);
+        crate::insert_if_missing(app, relationship::Relationships::load);
+        crate::insert_if_missing(app, storyline::Storylines::load);
+        crate::insert_if_missing(app, schedule::Schedules::load);
+        crate::insert_if_missing(app, behaviour::Behaviours::load);
+        crate::insert_if_missing(app, DialogSettings::from_env);
+        app.init_resource::<NpcAiSettings>()
+            .init_resource::<prompt::PromptTemplates>()
+            .init_resource::<ThinkScheduler>()
+            .add_event::<DialogPrompt>()
+            .add_event::<DialogReply>()
+            .add_event::<DialogCancel>()
+            .add_event::<storyline::StorylineEvent>()
+            .add_event::<perception::Observation>()
+            .add_systems(Startup, experiment::apply_time_scale)
+            .add_systems(
+                Update,
+                (
+                    experiment::assign_variants.in_set(GameSet::Time),
+                    (
+                        (apply_dialog_replies, update_npcs, think::dispatch_thoughts).chain(),
+                        behaviour::run_behaviours,
+                    )
+                        .in_set(GameSet::Simulation),
+                    (
+                        schedule::follow_schedules,
+                        plan::follow_plans,
+                        needs::seek_food,
+                        needs::share_food,
+                    )
+                        .chain()
+                        .in_set(GameSet::Decisions),
+                    perception::perceive
+                        .after(crate::animation::update_facing)
+                        .in_set(GameSet::Perception),
+                    (
+                        update_history,
+                        relationship::update_relationships,
+                        storyline::record_speech,
+                    )
+                        .in_set(GameSet::Memory),
+                    (
+                        storyline::evaluate_storylines,
+                        storyline::remember_storyline_events,
+                    )
+                        .chain(),
+                    experiment::count_starvation,
+                ),
+            )
+            .add_systems(Last, experiment::finish_experiment);
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub enum NPCState {
+    Idle,
+    Farming,
+    Traveling(String),
+    Sleeping,
+    /// A task authored only as a behaviour tree, e.g. `taking_a_break`.
+    Activity(String),
+}
+
+impl NPCState {
+    /// The task's name, which is also the name of the behaviour tree carrying it out.
+    pub fn name(&self) -> &str {
+        match self {
+            NPCState::Idle => "idle",
+            NPCState::Farming => "farming",
+            NPCState::Traveling(_) => "traveling",
+            NPCState::Sleeping => "sleeping",
+            NPCState::Activity(name) => name,
+        }
+    }
+
+    pub fn get_context(&self) -> String {
+        match self {
+            NPCState::Idle => "You are currently idle.".to_string(),
+            NPCState::Farming => "You are currently farming.".to_string(),
+            NPCState::Sleeping => "You are currently asleep at home.".to_string(),
+            NPCState::Traveling(destination) => {
+                format!("You are currently traveling to {}. ", destination)
+            }
+            NPCState::Activity(name) => format!("You are currently {}.", name.replace('_', " ")),
+        }
+    }
+
+    pub fn from_task_arguments(arguments: &serde_json::Value) -> Option<NPCState> {
+        let Some(task) = arguments["task"].as_str() else {
+            println!("Invalid task arguments: {}", arguments);
+            return None;
+        };
+        Some(match task {
+            "idle" => NPCState::Idle,
+            "farming" => NPCState::Farming,
+            "traveling" => {
+                if let Some(destination) = arguments["destination"].as_str() {
+                    NPCState::Traveling(destination.to_string())
+                } else {
+                    println!("Invalid destination: {}", arguments);
+                    NPCState::Idle
+                }
+            }
+            // the behaviour trees decide whether anyone knows how
+            activity => NPCState::Activity(activity.to_string()),
+        })
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Component)]
+pub struct NPC {
+    pub backstory: String,
+    pub chat_cooldown: f32,
+    pub history: Vec<Memory>,
+    pub state: NPCState,
+    pub food_decision_cooldown: f32,
+    /// Spoken to by the player since the NPC last thought, so the NPC thinks first.
+    pub addressed: bool,
+    /// How many of the oldest memories the summary being written covers.
+    pub summarizing: usize,
+    /// Steps the NPC is working through toward a goal, ahead of its schedule.
+    pub plan: Option<plan::Plan>,
+    /// The plan failed or something happened that may change it, so the NPC should plan again.
+    pub replan: bool,
+    /// Region the NPC sleeps in at night.
+    pub home: String,
+    pub path: navigation::NavPath,
+    pub behaviour: behaviour::Progress,
+}
+
+impl NPC {
+    pub const CHAT_COOLDOWN: f32 = 100.0;
+
+    pub fn remember(&mut self, time: clock::GameTime, actor: String, action: Action) {
+        self.history.push(Memory {
+            time,
+            actor,
+            action,
+        });
+    }
+
+    /// Switches to a task the model picked, which drops any plan the NPC was following.
+    fn set_task(&mut self, state: NPCState) {
+        self.plan = None;
+        self.state = state;
+    }
+
+    /// Starts the plan in `arguments`, shaped like the `set_plan` tool's, in place of the old one.
+    fn set_plan(&mut self, name: &str, arguments: &serde_json::Value) {
+        match plan::Plan::from_arguments(arguments) {
+            Ok(plan) => {
+                println!(
+                    "{} plans to {}: {}",
+                    name,
+                    plan.goal,
+                    plan.steps.iter().map(plan::PlanStep::describe).join(", ")
+                );
+                self.plan = Some(plan);
+                self.replan = false;
+            }
+            Err(e) => println!("Invalid plan arguments: {}: {}", e, arguments),
+        }
+    }
+
+    /// Replaces the memories the pending summary covers with `summary`.
+    fn summarize(&mut self, name: &str, summary: &str) {
+        let covered = self.summarizing.min(self.history.len());
+        self.summarizing = 0;
+        if covered == 0 || summary.is_empty() {
+            return;
+        }
+        let time = self.history[covered - 1].time;
+        self.history.splice(
+            ..covered,
+            [Memory {
+                time,
+                actor: name.to_string(),
+                action: Action::Event(format!("You remember: {}", summary)),
+            }],
+        );
+        println!("{} summarized {} memories: {}", name, covered, summary);
+    }
+}
+
+impl Default for NPC {
+    fn default() -> Self {
+        NPC {
+            backstory: "".to_string(),
+            chat_cooldown: NPC::CHAT_COOLDOWN / 2.0,
+            history: vec![],
+            state: NPCState::Idle,
+            food_decision_cooldown: 0.0,
+            addressed: false,
+            summarizing: 0,
+            plan: None,
+            replan: false,
+            home: "".to_string(),
+            path: navigation::NavPath::default(),
+            behaviour: behaviour::Progress::default(),
+        }
+    }
+}
+
+fn update_history(
+    clock: Res<GameClock>,
+    mut observations: EventReader<perception::Observation>,
+    mut npc_query: Query<(&mut NPC, &perception::Perception)>,
+    players: Query<&Character, With<Player>>,
+) {
+    for observation in observations.read() {
+        let Ok((mut npc, perception)) = npc_query.get_mut(observation.observer) else {
+            continue;
+        };
+        // the player talks to whoever is in earshot
+        if matches!(observation.action, Action::Talk(_))
+            && players
+                .iter()
+                .any(|player| player.name == observation.actor)
+        {
+            npc.addressed = true;
+        }
+        let action = match &observation.action {
+            // a voice from out of sight is remembered as just that
+            Action::Talk(speech)
+                if observation.sense == perception::Sense::Heard
+                    && !perception.visible.contains(&observation.actor) =>
+            {
+                Action::Event(format!(
+                    "You hear {} say \"{}\" from somewhere nearby.",
+                    observation.actor, speech
+                ))
+            }
+            action => action.clone(),
+        };
+        npc.remember(clock.now, observation.actor.clone(), action);
+    }
+}
+
+/// Builds a prompt for every NPC whose cooldown ran out and queues it with the think scheduler.
+#[allow(clippy::too_many_arguments)]
+fn update_npcs(
+    time: Res<Time>,
+    clock: Res<GameClock>,
+    settings: Res<NpcAiSettings>,
+    dialog_settings: Res<DialogSettings>,
+    prompt_templates: Res<prompt::PromptTemplates>,
+    behaviours: Res<behaviour::Behaviours>,
+    relationships: Res<relationship::Relationships>,
+    mut experiment: ResMut<experiment::Experiment>,
+    mut npc_query: Query<
+        (
+            Entity,
+            &mut NPC,
+            &Character,
+            &Transform,
+            &perception::Perception,
+            Option<&experiment::AssignedVariant>,
+        ),
+        Without<death::Dead>,
+    >,
+    character_query: Query<(&Character, &Transform), Without<death::Dead>>,
+    plant_query: Query<(&Transform, &Plant)>,
+    region_query: Query<&Region>,
+    player_query: Query<&Transform, With<Player>>,
+    mut scheduler: ResMut<ThinkScheduler>,
+) {
+    let tasks = behaviours.task_names();
+    for (npc_entity_id, mut npc, character, npc_location, perception, assigned_variant) in
+        &mut npc_query
+    {
+        if matches!(npc.state, NPCState::Sleeping) {
+            continue;
+        }
+        if npc.chat_cooldown > 0.0 {
+            npc.chat_cooldown -= time.delta_seconds();
+            continue;
+        }
+        npc.chat_cooldown = settings.chat_cooldown;
+
+        let name = character.name.clone();
+
+        let nearby_people = perception.visible.clone();
+        let context = ThoughtContext {
+            state: npc.state.clone(),
+            addressees: nearby_people.clone(),
+        };
+
+        let regions = region_query
+            .iter()
+            .filter(|region| region.range.contains(npc_location.translation.xy()))
+            .map(|region| region.name.clone())
+            .collect::<Vec<String>>();
+
+        let food_sources = needs::describe_food_sources(
+            &name,
+            npc_location.translation.xy(),
+            character_query.iter(),
+            plant_query.iter(),
+            region_query.iter(),
+        );
+
+        let summarizing = npc.history.len().saturating_sub(settings.keep_recent);
+        let role = if npc.history.len() > settings.summarize_after {
+            ModelRole::Summary
+        } else if npc.addressed {
+            ModelRole::Conversation
+        } else if npc.replan {
+            ModelRole::Planning
+        } else if !nearby_people.is_empty() {
+            ModelRole::Conversation
+        } else if matches!(npc.state, NPCState::Idle) && npc.plan.is_none() {
+            ModelRole::Planning
+        } else {
+            ModelRole::Bark
+        };
+
+        let prompt_context = prompt::PromptContext {
+            relationships: relationships.describe_for(&name),
+            name,
+            backstory: npc.backstory.clone(),
+            history: if role == ModelRole::Summary {
+                npc.history[..summarizing]
+                    .iter()
+                    .map(Memory::get_context)
+                    .collect()
+            } else {
+                npc.history
+                    .iter()
+                    .unique_by(|memory| (&memory.actor, &memory.action))
+                    .map(Memory::get_context)
+                    .collect()
+            },
+            time: clock.now.describe(),
+            regions,
+            nearby_people,
+            hunger: needs::Hunger::of(character.saturation).as_str(),
+            food_sources,
+            saturation: character.saturation,
+            inventory: character
+                .items
+                .iter()
+                .map(|(item, count)| prompt::InventoryEntry {
+                    item: item.to_string(),
+                    count: *count,
+                })
+                .collect(),
+            task: npc.state.get_context().trim().to_string(),
+            tasks: tasks.clone(),
+            goal: npc
+                .plan
+                .as_ref()
+                .map(|plan| plan.goal.clone())
+                .unwrap_or_default(),
+            plan: npc
+                .plan
+                .iter()
+                .flat_map(|plan| plan.remaining())
+                .map(plan::PlanStep::describe)
+                .collect(),
+            structured_output: dialog_settings.structured_output,
+            role: role.name(),
+            tools: role.uses_tools(),
+        };
+        let variant = assigned_variant.and_then(|variant| experiment.variant(variant));
+        let prompt_variant = variant
+            .and_then(|variant| variant.prompt_variant.as_deref())
+            .unwrap_or(&prompt_templates.active_variant);
+        let model = variant.and_then(|variant| variant.model.clone());
+        let temperature = variant.and_then(|variant| variant.temperature);
+        let variant_name = variant.map(|variant| variant.name.clone());
+
+        let messages = if role == ModelRole::Summary {
+            let request = match prompt_templates.render_summary(prompt_variant, &prompt_context) {
+                Ok(request) => request,
+                Err(e) => {
+                    println!(
+                        "Could not render summary for {}: {}",
+                        prompt_context.name, e
+                    );
+                    continue;
+                }
+            };
+            npc.summarizing = summarizing;
+            vec![OpenAIMessage {
+                role: "user".to_string(),
+                content: Some(request),
+                tool_calls: None,
+                name: None,
+            }]
+        } else {
+            let prompt = match prompt_templates.render(prompt_variant, &prompt_context) {
+                Ok(prompt) => prompt,
+                Err(e) => {
+                    println!("Could not render prompt for {}: {}", prompt_context.name, e);
+                    continue;
+                }
+            };
+            let mut messages = vec![OpenAIMessage {
+                role: "system".to_string(),
+                content: Some(prompt.system),
+                tool_calls: None,
+                name: None,
+            }];
+            if !prompt.context.is_empty() {
+                messages.push(OpenAIMessage {
+                    role: "user".to_string(),
+                    content: Some(prompt.context),
+                    tool_calls: None,
+                    name: None,
+                });
+            }
+            messages
+        };
+
+        if let Some(metrics) = experiment.metrics_mut(variant_name.as_deref()) {
+            metrics.requests += 1;
+        }
+
+        let near_player = player_query.iter().any(|player| {
+            player
+                .translation
+                .xy()
+                .distance(npc_location.translation.xy())
+                <= settings.near_player_distance
+        });
+        // a summary is housekeeping, the NPC still owes whoever addressed it an answer
+        let priority = if role == ModelRole::Summary {
+            ThinkPriority::Background
+        } else if npc.addressed {
+            ThinkPriority::Addressed
+        } else if near_player {
+            ThinkPriority::NearPlayer
+        } else {
+            ThinkPriority::Background
+        };
+        if role != ModelRole::Summary {
+            npc.addressed = false;
+        }
+        if role == ModelRole::Planning {
+            npc.replan = false;
+        }
+
+        scheduler.request(
+            DialogPrompt {
+                npc: npc_entity_id,
+                messages,
+                role,
+                tasks: tasks.clone(),
+                model,
+                temperature,
+                variant: variant_name,
+                request: 0,
+            },
+            priority,
+            context,
+            time.elapsed_seconds(),
+        );
+    }
+}
+
+/// Turns the model's replies into speech, emotes, task changes and plans, throwing away replies
+/// that no longer fit the NPC's situation.
+fn apply_dialog_replies(
+    mut replies: EventReader<DialogReply>,
+    mut npcs: Query<(&mut NPC, &mut Character, &perception::Perception)>,
+    others: Query<&Character, Without<NPC>>,
+    mut scheduler: ResMut<ThinkScheduler>,
+    mut experiment: ResMut<experiment::Experiment>,
+) {
+    if replies.is_empty() {
+        return;
+    }
+    let characters: Vec<String> = npcs
+        .iter()
+        .map(|(_, character, _)| character)
+        .chain(others.iter())
+        .map(|character| character.name.clone())
+        .collect();
+    for reply in replies.read() {
+        let thought = scheduler.finish(reply);
+        let Ok((mut npc, mut character, perception)) = npcs.get_mut(reply.npc) else {
+            continue;
+        };
+        let mut metrics = experiment.metrics_mut(reply.variant.as_deref());
+        let Some(thought) = thought else {
+            println!("Ignoring a reply to {}'s replaced request", character.name);
+            if let Some(metrics) = metrics.as_mut() {
+                metrics.stale_replies += 1;
+            }
+            continue;
+        };
+        let Some(message) = &reply.message else {
+            if let Some(metrics) = metrics.as_mut() {
+                metrics.failed_requests += 1;
+            }
+            continue;
+        };
+        if reply.role == ModelRole::Summary {
+            if let Some(summary) = message.content.as_deref().map(str::trim) {
+                npc.summarize(&character.name, summary);
+            }
+            // the summary took the NPC's turn to think
+            npc.chat_cooldown = 0.0;
+            continue;
+        }
+        if let Some(staleness) = thought.context.staleness(&npc.state, &perception.audible) {
+            println!(
+                "Discarding {}'s reply, it no longer fits: {:?}",
+                character.name, staleness
+            );
+            if let Some(metrics) = metrics.as_mut() {
+                metrics.stale_replies += 1;
+            }
+            // the situation moved on, so think about the new one right away
+            if staleness == Staleness::TaskChanged {
+                npc.chat_cooldown = 0.0;
+                npc.addressed |= thought.priority == ThinkPriority::Addressed;
+            }
+            continue;
+        }
+        if let Some(content) = message.content.as_deref() {
+            let response = speech::parse_npc_response(content, &character.name, &characters);
+            if let Some(metrics) = metrics.as_mut() {
+                metrics.emotes += response.emotes.len() as u32;
+                metrics.talks += response.speech.is_some() as u32;
+                metrics.parse_failures +=
+                    (response.is_empty() && !content.trim().is_empty()) as u32;
+            }
+            if response.is_empty() && !content.trim().is_empty() {
+                println!(
+                    "Could not parse response from {}: {:?}",
+                    character.name, content
+                );
+            }
+            for emote in response.emotes {
+                println!("Response: {} {}", character.name, emote);
+                character.actions.push(Action::Emote(emote));
+            }
+            if let Some(character_response) = response.speech {
+                println!("Response: {} says {}", character.name, character_response);
+                character.actions.push(Action::Talk(character_response));
+                if let Some(plan) = npc.plan.as_mut() {
+                    plan.spoke_to(&perception.audible);
+                }
+            }
+            if let Some(state) = response
+                .task_arguments
+                .as_ref()
+                .and_then(NPCState::from_task_arguments)
+            {
+                npc.set_task(state);
+            }
+            if let Some(arguments) = &response.plan_arguments {
+                npc.set_plan(&character.name, arguments);
+            }
+        };
+        if let Some(tool_calls) = &message.tool_calls {
+            if let Some(metrics) = metrics.as_mut() {
+                metrics.tool_calls += tool_calls.len() as u32;
+            }
+            for tool_call in tool_calls {
+                match tool_call.function.name.as_str() {
+                    "set_task" => {
+                        println!("Task arguments: {}", tool_call.function.arguments);
+                        if let Ok(task_args) = serde_json::from_str::<serde_json::Value>(
+                            tool_call.function.arguments.as_str(),
+                        ) {
+                            if let Some(state) = NPCState::from_task_arguments(&task_args) {
+                                npc.set_task(state);
+                            }
+                        } else {
+                            println!("Invalid task arguments: {}", tool_call.function.arguments);
+                        }
+                    }
+                    "set_plan" => {
+                        match serde_json::from_str::<serde_json::Value>(
+                            tool_call.function.arguments.as_str(),
+                        ) {
+                            Ok(arguments) => npc.set_plan(&character.name, &arguments),
+                            Err(_) => {
+                                println!("Invalid plan arguments: {}", tool_call.function.arguments)
+                            }
+                        }
+                    }
+                    unknown_tool => {
+                        println!("Unknown tool: {}", unknown_tool);
+                    }
+                }
+            }
+        }
+    }
+}
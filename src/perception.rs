@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+
+use crate::{
+    animation::Facing,
+    controls::{ActionState, InputAction},
+    death::Dead,
+    navigation::NavigationGrid,
+    Action, Character, NPC,
+};
+
+/// Anyone this close is noticed no matter which way the observer is facing.
+const NOTICE_RADIUS: f32 = 60.0;
+
+/// How far and how widely a character perceives the world around them.
+#[derive(Component, Clone)]
+pub struct Perception {
+    /// Speech within this distance is heard, even around corners.
+    pub hearing_radius: f32,
+    pub sight_radius: f32,
+    /// Width of the cone in front of the character they can see, in degrees.
+    pub field_of_view: f32,
+    /// Characters currently in sight, updated every frame.
+    pub visible: Vec<String>,
+    /// Characters within hearing range, whichever way the character faces, updated every frame.
+    pub audible: Vec<String>,
+}
+
+impl Default for Perception {
+    fn default() -> Self {
+        Perception {
+            hearing_radius: 600.0,
+            sight_radius: 400.0,
+            field_of_view: 200.0,
+            visible: vec![],
+            audible: vec![],
+        }
+    }
+}
+
+impl Perception {
+    fn can_see(&self, position: Vec2, facing: Vec2, target: Vec2, grid: &NavigationGrid) -> bool {
+        let offset = target - position;
+        let distance = offset.length();
+        if distance > self.sight_radius {
+            return false;
+        }
+        let in_view = distance < NOTICE_RADIUS
+            || facing.angle_between(offset).abs().to_degrees() <= self.field_of_view / 2.0;
+        in_view && grid.line_of_sight(position, target)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sense {
+    Heard,
+    Saw,
+}
+
+/// An NPC noticed someone doing something.
+#[derive(Event)]
+pub struct Observation {
+    pub observer: Entity,
+    pub observer_name: String,
+    pub actor: String,
+    pub actor_position: Vec2,
+    pub action: Action,
+    pub sense: Sense,
+}
+
+/// Toggled with F3 by default to draw every NPC's hearing and sight ranges.
+#[derive(Resource, Default)]
+pub struct PerceptionOverlay {
+    pub enabled: bool,
+}
+
+/// Works out who each NPC can see and turns the actions they notice into observations.
+pub fn perceive(
+    grid: Res<NavigationGrid>,
+    mut observers: Query<
+        (Entity, &Character, &Transform, &mut Perception, &Facing),
+        (With<NPC>, Without<Dead>),
+    >,
+    actors: Query<(&Character, &Transform), Without<Dead>>,
+    mut observations: EventWriter<Observation>,
+) {
+    for (observer, observer_character, observer_transform, mut perception, facing) in &mut observers
+    {
+        let position = observer_transform.translation.xy();
+        perception.visible.clear();
+        perception.audible.clear();
+        for (actor, actor_transform) in &actors {
+            let actor_position = actor_transform.translation.xy();
+            let is_self = actor.name == observer_character.name;
+            let seen =
+                !is_self && perception.can_see(position, facing.direction, actor_position, &grid);
+            if seen {
+                perception.visible.push(actor.name.clone());
+            }
+            let heard = position.distance(actor_position) < perception.hearing_radius;
+            if heard && !is_self {
+                perception.audible.push(actor.name.clone());
+            }
+            for action in &actor.actions {
+                // everyone knows what they did themselves
+                let sense = match action {
+                    _ if is_self => Sense::Saw,
+                    Action::Talk(_) if heard => Sense::Heard,
+                    _ if seen => Sense::Saw,
+                    _ => continue,
+                };
+                observations.send(Observation {
+                    observer,
+                    observer_name: observer_character.name.clone(),
+                    actor: actor.name.clone(),
+                    actor_position,
+                    action: action.clone(),
+                    sense,
+                });
+            }
+        }
+    }
+}
+
+pub fn toggle_overlay(actions: Res<ActionState>, mut overlay: ResMut<PerceptionOverlay>) {
+    if actions.just_pressed(InputAction::PerceptionOverlay) {
+        overlay.enabled = !overlay.enabled;
+    }
+}
+
+pub fn draw_overlay(
+    overlay: Res<PerceptionOverlay>,
+    mut gizmos: Gizmos,
+    observers: Query<(&Transform, &Perception, &Facing), (With<NPC>, Without<Dead>)>,
+    characters: Query<(&Character, &Transform)>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+    for (transform, perception, facing) in &observers {
+        let position = transform.translation.xy();
+        gizmos.circle_2d(position, perception.hearing_radius, Color::YELLOW);
+        gizmos
+            .arc_2d(
+                position,
+                facing.direction.x.atan2(facing.direction.y),
+                perception.field_of_view.to_radians(),
+                perception.sight_radius,
+                Color::CYAN,
+            )
+            .segments(48);
+        let half_view = perception.field_of_view.to_radians() / 2.0;
+        for edge in [-half_view, half_view] {
+            let edge = Vec2::from_angle(edge).rotate(facing.direction);
+            gizmos.line_2d(
+                position,
+                position + edge * perception.sight_radius,
+                Color::CYAN,
+            );
+        }
+        for (character, character_transform) in &characters {
+            if perception.visible.contains(&character.name) {
+                gizmos.line_2d(position, character_transform.translation.xy(), Color::CYAN);
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Multi-step plans the model writes for an NPC, such as "go to Bill's Farm, talk to Bill about
+//! work, return home", carried out one step at a time by setting the NPC's task. A step that
+//! fails drops the plan and has the NPC think up a new one.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{GameClock, GameTime},
+    death::Dead,
+    perception::Perception,
+    Action, Character, NPCState, Region, NPC,
+};
+
+/// Most steps a plan may have, longer ones are cut short.
+pub const MAX_STEPS: usize = 8;
+/// Game minutes a travel or talk step may take before it counts as failed.
+const STEP_TIMEOUT_MINUTES: f32 = 180.0;
+/// Seconds an NPC waits at most before speaking to the person a talk step is about.
+const TALK_COOLDOWN: f32 = 10.0;
+
+fn default_minutes() -> f32 {
+    60.0
+}
+
+/// One thing to do on the way to a goal.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlanStep {
+    Travel {
+        destination: String,
+    },
+    /// Farm wherever the NPC is for `minutes` of game time.
+    Farm {
+        #[serde(default = "default_minutes")]
+        minutes: f32,
+    },
+    /// Say something to `person` once they are in sight.
+    Talk {
+        person: String,
+        #[serde(default)]
+        topic: String,
+    },
+    ReturnHome,
+    Wait {
+        #[serde(default = "default_minutes")]
+        minutes: f32,
+    },
+}
+
+impl PlanStep {
+    /// The step as something to do, e.g. "talk to Bill about work".
+    pub fn describe(&self) -> String {
+        match self {
+            PlanStep::Travel { destination } => format!("go to {}", destination),
+            PlanStep::Farm { minutes } => format!("farm for {:.0} minutes", minutes),
+            PlanStep::Talk { person, topic } if topic.is_empty() => format!("talk to {}", person),
+            PlanStep::Talk { person, topic } => format!("talk to {} about {}", person, topic),
+            PlanStep::ReturnHome => "return home".to_string(),
+            PlanStep::Wait { minutes } => format!("wait for {:.0} minutes", minutes),
+        }
+    }
+}
+
+/// Where an NPC is in its plan.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Plan {
+    pub goal: String,
+    pub steps: Vec<PlanStep>,
+    /// Index of the step being carried out.
+    #[serde(default)]
+    pub current: usize,
+    /// When the current step started, `None` until it has.
+    #[serde(default)]
+    pub step_started: Option<GameTime>,
+    /// The NPC spoke to the person the current talk step is about.
+    #[serde(default)]
+    pub talked: bool,
+}
+
+impl Plan {
+    /// Reads a plan in the shape of the `set_plan` tool's arguments.
+    pub fn from_arguments(arguments: &serde_json::Value) -> Result<Plan, String> {
+        #[derive(Deserialize)]
+        struct Arguments {
+            goal: String,
+            steps: Vec<PlanStep>,
+        }
+        let Arguments { goal, mut steps } =
+            serde_json::from_value(arguments.clone()).map_err(|e| e.to_string())?;
+        if steps.is_empty() {
+            return Err("the plan has no steps".to_string());
+        }
+        steps.truncate(MAX_STEPS);
+        Ok(Plan {
+            goal,
+            steps,
+            current: 0,
+            step_started: None,
+            talked: false,
+        })
+    }
+
+    pub fn current_step(&self) -> Option<&PlanStep> {
+        self.steps.get(self.current)
+    }
+
+    /// The steps left, the current one first.
+    pub fn remaining(&self) -> &[PlanStep] {
+        &self.steps[self.current.min(self.steps.len())..]
+    }
+
+    /// Notes that the NPC said something within earshot of `listeners`, which finishes a talk step
+    /// with one of them.
+    pub fn spoke_to(&mut self, listeners: &[String]) {
+        if let Some(PlanStep::Talk { person, .. }) = self.current_step() {
+            self.talked |= listeners.contains(person);
+        }
+    }
+
+    fn advance(&mut self) {
+        self.current += 1;
+        self.step_started = None;
+        self.talked = false;
+    }
+}
+
+enum StepOutcome {
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// Starts each NPC's current plan step, moves on once it is done and drops the plan when it
+/// fails, leaving the NPC to plan again. Runs after the schedules, which leave NPCs with a plan
+/// alone.
+pub fn follow_plans(
+    clock: Res<GameClock>,
+    mut npcs: Query<(&mut NPC, &Character, &Transform, &Perception), Without<Dead>>,
+    regions: Query<&Region>,
+) {
+    for (mut npc, character, transform, perception) in &mut npcs {
+        let Some(mut plan) = npc.plan.take() else {
+            continue;
+        };
+        let position = transform.translation.xy();
+        let is_in = |region: &str| {
+            regions
+                .iter()
+                .any(|candidate| candidate.name == region && candidate.range.contains(position))
+        };
+
+        let outcome = match plan.current_step().cloned() {
+            None => StepOutcome::Done,
+            Some(step) => {
+                let started = match plan.step_started {
+                    Some(started) => started,
+                    None => {
+                        println!("{} sets out to {}", character.name, step.describe());
+                        plan.step_started = Some(clock.now);
+                        npc.state = match &step {
+                            PlanStep::Travel { destination } => {
+                                NPCState::Traveling(destination.clone())
+                            }
+                            PlanStep::ReturnHome => NPCState::Traveling(npc.home.clone()),
+                            PlanStep::Farm { .. } => NPCState::Farming,
+                            PlanStep::Talk { .. } | PlanStep::Wait { .. } => NPCState::Idle,
+                        };
+                        clock.now
+                    }
+                };
+                let elapsed = clock.now.0 - started.0;
+                match &step {
+                    PlanStep::Travel { destination } => {
+                        travel_outcome(&npc.state, destination, is_in(destination), elapsed)
+                    }
+                    PlanStep::ReturnHome if npc.home.is_empty() => StepOutcome::Done,
+                    PlanStep::ReturnHome => {
+                        travel_outcome(&npc.state, &npc.home, is_in(&npc.home), elapsed)
+                    }
+                    PlanStep::Farm { minutes } => {
+                        if elapsed >= *minutes {
+                            npc.state = NPCState::Idle;
+                            StepOutcome::Done
+                        } else if npc.state != NPCState::Farming {
+                            StepOutcome::Failed("you had to stop farming".to_string())
+                        } else {
+                            StepOutcome::Running
+                        }
+                    }
+                    PlanStep::Talk { person, .. } => {
+                        if plan.talked {
+                            StepOutcome::Done
+                        } else if elapsed > STEP_TIMEOUT_MINUTES {
+                            StepOutcome::Failed(format!("{} was nowhere to be found", person))
+                        } else {
+                            // speak up soon rather than when the NPC would next think anyway
+                            if perception.visible.contains(person) {
+                                npc.chat_cooldown = npc.chat_cooldown.min(TALK_COOLDOWN);
+                            }
+                            StepOutcome::Running
+                        }
+                    }
+                    PlanStep::Wait { minutes } if elapsed >= *minutes => StepOutcome::Done,
+                    PlanStep::Wait { .. } => StepOutcome::Running,
+                }
+            }
+        };
+
+        match outcome {
+            StepOutcome::Running => npc.plan = Some(plan),
+            StepOutcome::Done => {
+                plan.advance();
+                if plan.current_step().is_some() {
+                    npc.plan = Some(plan);
+                } else {
+                    println!("{} finished their plan to {}", character.name, plan.goal);
+                    npc.remember(
+                        clock.now,
+                        character.name.clone(),
+                        Action::Event(format!("You finished your plan to {}.", plan.goal)),
+                    );
+                }
+            }
+            StepOutcome::Failed(reason) => {
+                println!(
+                    "{} gives up on their plan to {}: {}",
+                    character.name, plan.goal, reason
+                );
+                npc.remember(
+                    clock.now,
+                    character.name.clone(),
+                    Action::Event(format!(
+                        "Your plan to {} failed because {}.",
+                        plan.goal, reason
+                    )),
+                );
+                npc.replan = true;
+                npc.chat_cooldown = 0.0;
+            }
+        }
+    }
+}
+
+fn travel_outcome(state: &NPCState, destination: &str, arrived: bool, elapsed: f32) -> StepOutcome {
+    if arrived {
+        StepOutcome::Done
+    } else if *state != NPCState::Traveling(destination.to_string()) {
+        StepOutcome::Failed(format!("you could not get to {}", destination))
+    } else if elapsed > STEP_TIMEOUT_MINUTES {
+        StepOutcome::Failed(format!("the way to {} took too long", destination))
+    } else {
+        StepOutcome::Running
+    }
+}
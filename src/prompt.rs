@@ -0,0 +1,218 @@
+use std::{env, fs, path::Path};
+
+use bevy::prelude::*;
+use minijinja::Environment;
+use serde::Serialize;
+
+pub const PROMPT_DIRECTORY: &str = "assets/prompts";
+pub const DEFAULT_VARIANT: &str = "default";
+
+const SYSTEM_TEMPLATE: &str = "system.j2";
+const CONTEXT_TEMPLATE: &str = "context.j2";
+/// Optional, variants without one use the default variant's.
+const SUMMARY_TEMPLATE: &str = "summary.j2";
+
+/// World facts exposed to the prompt templates.
+#[derive(Serialize, Default)]
+pub struct PromptContext {
+    pub name: String,
+    pub backstory: String,
+    /// Memories prefixed with when they happened, e.g. "[Day 2 14:05] Jeff harvests."
+    pub history: Vec<String>,
+    /// Sentence such as "It is the afternoon of day 3 of spring (14:05)."
+    pub time: String,
+    pub regions: Vec<String>,
+    /// Sentences such as "You feel warmly toward Steve."
+    pub relationships: Vec<String>,
+    pub nearby_people: Vec<String>,
+    /// One of `starving`, `hungry` or `fed`.
+    pub hunger: &'static str,
+    /// Where food can be found, e.g. "at Bill's Farm (12 ripe plants)".
+    pub food_sources: Vec<String>,
+    pub saturation: f32,
+    pub inventory: Vec<InventoryEntry>,
+    pub task: String,
+    /// Tasks the NPC may switch to, e.g. `farming` or `taking_a_break`.
+    pub tasks: Vec<String>,
+    /// What the NPC's plan is for, empty without a plan.
+    pub goal: String,
+    /// The plan's steps still to do, the current one first, e.g. "talk to Bill about work".
+    pub plan: Vec<String>,
+    pub structured_output: bool,
+    /// One of `bark`, `conversation`, `planning` or `summary`.
+    pub role: &'static str,
+    /// Whether the request offers functions to change the NPC's task or plan.
+    pub tools: bool,
+}
+
+#[derive(Serialize)]
+pub struct InventoryEntry {
+    pub item: String,
+    pub count: u32,
+}
+
+pub struct RenderedPrompt {
+    pub system: String,
+    pub context: String,
+}
+
+/// Prompt templates loaded from `assets/prompts/<variant>/`, one directory per variant.
+#[derive(Resource)]
+pub struct PromptTemplates {
+    env: Environment<'static>,
+    /// Variant used for every NPC, picked with the `PROMPT_VARIANT` environment variable.
+    pub active_variant: String,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        PromptTemplates::load(PROMPT_DIRECTORY)
+    }
+}
+
+impl PromptTemplates {
+    pub fn load(directory: impl AsRef<Path>) -> Self {
+        let mut environment = Environment::new();
+        environment.add_filter("join_list", join_list);
+        let mut variants = vec![];
+
+        match fs::read_dir(directory.as_ref()) {
+            Ok(entries) => {
+                for entry in entries.flatten() {
+                    if !entry.path().is_dir() {
+                        continue;
+                    }
+                    let variant = entry.file_name().to_string_lossy().to_string();
+                    for template in [SYSTEM_TEMPLATE, CONTEXT_TEMPLATE] {
+                        let path = entry.path().join(template);
+                        match fs::read_to_string(&path) {
+                            Ok(source) => {
+                                if let Err(e) = environment
+                                    .add_template_owned(format!("{variant}/{template}"), source)
+                                {
+                                    println!("Invalid prompt template {}: {}", path.display(), e);
+                                }
+                            }
+                            Err(e) => {
+                                println!("Could not read prompt template {}: {}", path.display(), e)
+                            }
+                        }
+                    }
+                    let path = entry.path().join(SUMMARY_TEMPLATE);
+                    if let Ok(source) = fs::read_to_string(&path) {
+                        if let Err(e) = environment
+                            .add_template_owned(format!("{variant}/{SUMMARY_TEMPLATE}"), source)
+                        {
+                            println!("Invalid prompt template {}: {}", path.display(), e);
+                        }
+                    }
+                    variants.push(variant);
+                }
+            }
+            Err(e) => println!(
+                "Could not read prompt directory {}: {}",
+                directory.as_ref().display(),
+                e
+            ),
+        }
+
+        // keep the game playable without the assets directory
+        if !variants.iter().any(|variant| variant == DEFAULT_VARIANT) {
+            environment
+                .add_template(
+                    "default/system.j2",
+                    include_str!("../assets/prompts/default/system.j2"),
+                )
+                .unwrap();
+            environment
+                .add_template(
+                    "default/context.j2",
+                    include_str!("../assets/prompts/default/context.j2"),
+                )
+                .unwrap();
+            variants.push(DEFAULT_VARIANT.to_string());
+        }
+        if environment.get_template("default/summary.j2").is_err() {
+            environment
+                .add_template(
+                    "default/summary.j2",
+                    include_str!("../assets/prompts/default/summary.j2"),
+                )
+                .unwrap();
+        }
+
+        let active_variant = match env::var("PROMPT_VARIANT") {
+            Ok(variant) if variants.contains(&variant) => variant,
+            Ok(variant) => {
+                println!(
+                    "Unknown prompt variant {}, using {}",
+                    variant, DEFAULT_VARIANT
+                );
+                DEFAULT_VARIANT.to_string()
+            }
+            Err(_) => DEFAULT_VARIANT.to_string(),
+        };
+
+        PromptTemplates {
+            env: environment,
+            active_variant,
+        }
+    }
+
+    pub fn render(
+        &self,
+        variant: &str,
+        context: &PromptContext,
+    ) -> Result<RenderedPrompt, minijinja::Error> {
+        Ok(RenderedPrompt {
+            system: self.render_template(variant, SYSTEM_TEMPLATE, context)?,
+            context: self.render_template(variant, CONTEXT_TEMPLATE, context)?,
+        })
+    }
+
+    /// The request to condense the memories in `context.history`.
+    pub fn render_summary(
+        &self,
+        variant: &str,
+        context: &PromptContext,
+    ) -> Result<String, minijinja::Error> {
+        let variant = if self
+            .env
+            .get_template(&format!("{variant}/{SUMMARY_TEMPLATE}"))
+            .is_ok()
+        {
+            variant
+        } else {
+            DEFAULT_VARIANT
+        };
+        self.render_template(variant, SUMMARY_TEMPLATE, context)
+    }
+
+    fn render_template(
+        &self,
+        variant: &str,
+        template: &str,
+        context: &PromptContext,
+    ) -> Result<String, minijinja::Error> {
+        let rendered = self
+            .env
+            .get_template(&format!("{variant}/{template}"))?
+            .render(context)?;
+        // sections that render empty shouldn't leave blank lines behind
+        Ok(rendered
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// Joins names as "A", "A and B" or "A, B and C".
+pub fn join_list(items: Vec<String>) -> String {
+    match items.split_last() {
+        None => "".to_string(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+    }
+}
@@ -0,0 +1,175 @@
+use std::{collections::HashMap, fs};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{perception::Observation, Action, Region};
+
+pub const RELATIONSHIPS_PATH: &str = "assets/relationships.json";
+
+const LIMIT: f32 = 100.0;
+/// Values beyond this are strong enough to mention in prompts.
+const NOTABLE: f32 = 30.0;
+
+const FRIENDLY_WORDS: &[&str] = &[
+    "thank", "friend", "love", "help", "please", "welcome", "glad", "kind",
+];
+const HOSTILE_WORDS: &[&str] = &[
+    "hate", "stupid", "fool", "idiot", "leave me", "go away", "shut up", "liar",
+];
+
+/// How one character feels about another, each in the range -100 to 100.
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+pub struct Relationship {
+    #[serde(default)]
+    pub affection: f32,
+    #[serde(default)]
+    pub trust: f32,
+    #[serde(default)]
+    pub respect: f32,
+}
+
+impl Relationship {
+    fn adjust(&mut self, affection: f32, trust: f32, respect: f32) {
+        self.affection = (self.affection + affection).clamp(-LIMIT, LIMIT);
+        self.trust = (self.trust + trust).clamp(-LIMIT, LIMIT);
+        self.respect = (self.respect + respect).clamp(-LIMIT, LIMIT);
+    }
+
+    /// Prompt sentences for the feelings strong enough to matter, e.g. "You feel warmly toward Steve."
+    pub fn describe(&self, name: &str) -> Vec<String> {
+        let feelings = [
+            (self.affection, "feel warmly toward", "dislike"),
+            (self.trust, "trust", "distrust"),
+            (self.respect, "respect", "look down on"),
+        ];
+        feelings
+            .iter()
+            .filter_map(|&(value, positive, negative)| {
+                if value >= NOTABLE {
+                    Some(format!("You {} {}.", positive, name))
+                } else if value <= -NOTABLE {
+                    Some(format!("You {} {}.", negative, name))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthoredRelationship {
+    from: String,
+    to: String,
+    #[serde(flatten)]
+    relationship: Relationship,
+}
+
+/// Directed relationship graph keyed by character names: `(from, to)` is how `from` feels about `to`.
+#[derive(Resource, Default)]
+pub struct Relationships {
+    pairs: HashMap<(String, String), Relationship>,
+}
+
+impl Relationships {
+    /// Loads the starting relationships authored in `assets/relationships.json`.
+    pub fn load() -> Self {
+        let authored = fs::read_to_string(RELATIONSHIPS_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                serde_json::from_str::<Vec<AuthoredRelationship>>(&source)
+                    .map_err(|e| e.to_string())
+            });
+        match authored {
+            Ok(authored) => Relationships {
+                pairs: authored
+                    .into_iter()
+                    .map(|entry| ((entry.from, entry.to), entry.relationship))
+                    .collect(),
+            },
+            Err(e) => {
+                println!("Could not load {}: {}", RELATIONSHIPS_PATH, e);
+                Relationships::default()
+            }
+        }
+    }
+
+    pub fn get(&self, from: &str, to: &str) -> Relationship {
+        self.pairs
+            .get(&(from.to_string(), to.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn get_mut(&mut self, from: &str, to: &str) -> &mut Relationship {
+        self.pairs
+            .entry((from.to_string(), to.to_string()))
+            .or_default()
+    }
+
+    /// Everyone `from` has feelings about, sorted by name.
+    pub fn known_by(&self, from: &str) -> Vec<(&str, Relationship)> {
+        let mut known = self
+            .pairs
+            .iter()
+            .filter(|((pair_from, _), _)| pair_from == from)
+            .map(|((_, to), relationship)| (to.as_str(), *relationship))
+            .collect::<Vec<_>>();
+        known.sort_by_key(|(to, _)| *to);
+        known
+    }
+
+    /// Prompt sentences describing how `from` feels about everyone else.
+    pub fn describe_for(&self, from: &str) -> Vec<String> {
+        self.known_by(from)
+            .into_iter()
+            .flat_map(|(to, relationship)| relationship.describe(to))
+            .collect()
+    }
+}
+
+/// Rule-based evaluator: NPCs update how they feel about characters they notice acting.
+pub fn update_relationships(
+    mut relationships: ResMut<Relationships>,
+    mut observations: EventReader<Observation>,
+    regions: Query<&Region>,
+) {
+    for observation in observations.read() {
+        if observation.actor == observation.observer_name {
+            continue;
+        }
+        let observer = &observation.observer_name;
+        let relationship = relationships.get_mut(observer, &observation.actor);
+        match &observation.action {
+            Action::Talk(speech) => {
+                let speech = speech.to_lowercase();
+                // spending time together builds familiarity
+                relationship.adjust(0.5, 0.2, 0.0);
+                if FRIENDLY_WORDS.iter().any(|word| speech.contains(word)) {
+                    relationship.adjust(2.0, 1.0, 0.0);
+                }
+                if HOSTILE_WORDS.iter().any(|word| speech.contains(word)) {
+                    relationship.adjust(-3.0, -1.0, -1.0);
+                }
+            }
+            Action::Harvest(_) => {
+                let owns_field = regions.iter().any(|region| {
+                    region.name.starts_with(&format!("{}'s", observer))
+                        && region.range.contains(observation.actor_position)
+                });
+                if owns_field && relationship.trust < NOTABLE {
+                    // taking crops from a field you weren't trusted with is theft
+                    relationship.adjust(-2.0, -3.0, 0.0);
+                } else {
+                    relationship.adjust(0.0, 0.0, 0.2);
+                }
+            }
+            Action::Give(recipient) if recipient == observer => {
+                relationship.adjust(5.0, 3.0, 0.0);
+            }
+            Action::Give(_) => relationship.adjust(0.5, 0.0, 0.5),
+            Action::Eat | Action::Emote(_) | Action::Event(_) => {}
+        }
+    }
+}
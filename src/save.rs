@@ -0,0 +1,156 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{GameClock, GameTime},
+    death::{stand_up, Dead},
+    plan::Plan,
+    Character, Item, Memory, NPCState, NPC,
+};
+
+pub const SAVE_DIRECTORY: &str = "saves";
+pub const QUICKSAVE_PATH: &str = "saves/quicksave.json";
+
+#[derive(Event)]
+pub struct SaveGame;
+
+#[derive(Event)]
+pub struct LoadGame;
+
+#[derive(Serialize, Deserialize)]
+struct SavedDeath {
+    cause: String,
+    time_of_death: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedNpc {
+    state: NPCState,
+    chat_cooldown: f32,
+    history: Vec<Memory>,
+    /// Saves from before plans have none.
+    #[serde(default)]
+    plan: Option<Plan>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedCharacter {
+    name: String,
+    position: [f32; 2],
+    saturation: f32,
+    items: Vec<(Item, u32)>,
+    dead: Option<SavedDeath>,
+    npc: Option<SavedNpc>,
+}
+
+/// Everything needed to put the villagers back where they were.
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    time: GameTime,
+    characters: Vec<SavedCharacter>,
+}
+
+pub fn save_game(
+    mut requests: EventReader<SaveGame>,
+    clock: Res<GameClock>,
+    characters: Query<(&Character, &Transform, Option<&NPC>, Option<&Dead>)>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    let save = SaveFile {
+        time: clock.now,
+        characters: characters
+            .iter()
+            .map(|(character, transform, npc, dead)| SavedCharacter {
+                name: character.name.clone(),
+                position: transform.translation.xy().to_array(),
+                saturation: character.saturation,
+                items: character.items.clone(),
+                dead: dead.map(|dead| SavedDeath {
+                    cause: dead.cause.clone(),
+                    time_of_death: dead.time_of_death,
+                }),
+                npc: npc.map(|npc| SavedNpc {
+                    state: npc.state.clone(),
+                    chat_cooldown: npc.chat_cooldown,
+                    history: npc.history.clone(),
+                    plan: npc.plan.clone(),
+                }),
+            })
+            .collect(),
+    };
+    let result = fs::create_dir_all(SAVE_DIRECTORY)
+        .map_err(|e| e.to_string())
+        .and_then(|_| serde_json::to_string_pretty(&save).map_err(|e| e.to_string()))
+        .and_then(|json| fs::write(QUICKSAVE_PATH, json).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => println!("Saved game to {}", QUICKSAVE_PATH),
+        Err(e) => println!("Could not save game: {}", e),
+    }
+}
+
+pub fn load_game(
+    mut commands: Commands,
+    mut requests: EventReader<LoadGame>,
+    mut clock: ResMut<GameClock>,
+    mut characters: Query<(
+        Entity,
+        &mut Character,
+        &mut Transform,
+        &mut Sprite,
+        Option<&mut NPC>,
+        Option<&Dead>,
+    )>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    let save = fs::read_to_string(QUICKSAVE_PATH)
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str::<SaveFile>(&json).map_err(|e| e.to_string()));
+    let save = match save {
+        Ok(save) => save,
+        Err(e) => {
+            println!("Could not load {}: {}", QUICKSAVE_PATH, e);
+            return;
+        }
+    };
+
+    clock.now = save.time;
+    for saved in save.characters {
+        let Some((entity, mut character, mut transform, mut sprite, npc, dead)) = characters
+            .iter_mut()
+            .find(|(_, character, ..)| character.name == saved.name)
+        else {
+            println!("Saved character {} is not in the world", saved.name);
+            continue;
+        };
+        character.saturation = saved.saturation;
+        character.items = saved.items;
+        character.actions.clear();
+        transform.translation = Vec2::from_array(saved.position).extend(transform.translation.z);
+        if let (Some(mut npc), Some(saved_npc)) = (npc, saved.npc) {
+            npc.state = saved_npc.state;
+            npc.chat_cooldown = saved_npc.chat_cooldown;
+            npc.history = saved_npc.history;
+            npc.plan = saved_npc.plan;
+        }
+        match saved.dead {
+            Some(saved_death) => {
+                commands.entity(entity).insert(Dead {
+                    cause: saved_death.cause,
+                    time_of_death: saved_death.time_of_death,
+                });
+            }
+            None if dead.is_some() => {
+                stand_up(&mut transform, &mut sprite);
+                commands.entity(entity).remove::<Dead>();
+            }
+            None => {}
+        }
+    }
+    println!("Loaded game from {}", QUICKSAVE_PATH);
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fs};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    clock::GameClock,
+    death::Dead,
+    needs::{self, Hunger},
+    Action, Character, NPCState, Region, NPC,
+};
+
+pub const SCHEDULES_PATH: &str = "assets/schedules.json";
+
+/// What a character does during one block of their day.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "activity", rename_all = "snake_case")]
+pub enum Activity {
+    /// Get up and potter about wherever they slept.
+    Wake,
+    /// Farm in `region`, or at home when no region is given.
+    Farm {
+        #[serde(default)]
+        region: Option<String>,
+    },
+    /// Head home for a meal, eating whatever food they carry.
+    Eat,
+    Visit {
+        region: String,
+    },
+    /// Go home and sleep, unless they are starving.
+    Sleep,
+    /// Carry out an activity from `assets/behaviours.json` in `region`, or wherever they are.
+    Do {
+        behaviour: String,
+        #[serde(default)]
+        region: Option<String>,
+    },
+}
+
+impl Activity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Activity::Wake => "wake",
+            Activity::Farm { .. } => "farm",
+            Activity::Eat => "eat",
+            Activity::Visit { .. } => "visit",
+            Activity::Sleep => "sleep",
+            Activity::Do { .. } => "do",
+        }
+    }
+
+    /// The state that carries out this activity for an NPC standing at `position`.
+    fn state(&self, npc: &NPC, position: Vec2, regions: &[&Region]) -> NPCState {
+        let go_to = |region: &str, there: NPCState| {
+            let arrived = regions
+                .iter()
+                .any(|candidate| candidate.name == region && candidate.range.contains(position));
+            if arrived || region.is_empty() {
+                there
+            } else {
+                NPCState::Traveling(region.to_string())
+            }
+        };
+        match self {
+            Activity::Wake => NPCState::Idle,
+            Activity::Farm { region } => {
+                go_to(region.as_deref().unwrap_or(&npc.home), NPCState::Farming)
+            }
+            Activity::Eat => go_to(&npc.home, NPCState::Idle),
+            Activity::Visit { region } => go_to(region, NPCState::Idle),
+            Activity::Sleep => go_to(&npc.home, NPCState::Sleeping),
+            Activity::Do { behaviour, region } => go_to(
+                region.as_deref().unwrap_or_default(),
+                NPCState::Activity(behaviour.clone()),
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ScheduleBlock {
+    /// Hour of the day the block starts, e.g. `6.5` for half past six.
+    pub at: f32,
+    #[serde(flatten)]
+    pub activity: Activity,
+}
+
+/// Daily routines keyed by character name, with each block running until the next one starts.
+#[derive(Resource, Default)]
+pub struct Schedules {
+    routines: HashMap<String, Vec<ScheduleBlock>>,
+    /// Index of the block each character last started.
+    current: HashMap<String, usize>,
+}
+
+impl Schedules {
+    /// Loads the routines authored in `assets/schedules.json`.
+    pub fn load() -> Self {
+        let routines = fs::read_to_string(SCHEDULES_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                serde_json::from_str::<HashMap<String, Vec<ScheduleBlock>>>(&source)
+                    .map_err(|e| e.to_string())
+            });
+        let mut routines = routines.unwrap_or_else(|e| {
+            println!("Could not load {}: {}", SCHEDULES_PATH, e);
+            HashMap::new()
+        });
+        for blocks in routines.values_mut() {
+            blocks.sort_by(|a, b| a.at.total_cmp(&b.at));
+        }
+        Schedules {
+            routines,
+            ..default()
+        }
+    }
+
+    /// The block `name` should be in at `hour`, wrapping around to yesterday's last block
+    /// before the first one of the day starts.
+    pub fn block_at(&self, name: &str, hour: f32) -> Option<(usize, &ScheduleBlock)> {
+        let blocks = self.routines.get(name)?;
+        let index = blocks
+            .iter()
+            .rposition(|block| block.at <= hour)
+            .or(blocks.len().checked_sub(1))?;
+        Some((index, &blocks[index]))
+    }
+}
+
+/// Drives NPC states from their schedules. A new block always takes over, while within a block the
+/// schedule only steps in once an NPC is idle, so tasks set by the model or by hunger run to
+/// completion before the routine resumes. NPCs following a plan are left to it until bedtime.
+pub fn follow_schedules(
+    clock: Res<GameClock>,
+    mut schedules: ResMut<Schedules>,
+    mut npcs: Query<(&mut NPC, &mut Character, &Transform), Without<Dead>>,
+    regions: Query<&Region>,
+) {
+    let regions = regions.iter().collect::<Vec<_>>();
+    let hour = clock.now.hour();
+    for (mut npc, mut character, transform) in &mut npcs {
+        let Some((index, block)) = schedules.block_at(&character.name, hour) else {
+            continue;
+        };
+        let activity = block.activity.clone();
+        let started = schedules.current.get(&character.name) != Some(&index);
+        if started {
+            schedules.current.insert(character.name.clone(), index);
+            println!("{} starts to {}", character.name, activity.name());
+            if activity == Activity::Eat && needs::food_count(&character) > 0 {
+                character.actions.push(Action::Eat);
+            }
+            if activity == Activity::Sleep {
+                if let Some(plan) = npc.plan.take() {
+                    println!("{} puts off their plan to {}", character.name, plan.goal);
+                }
+            }
+        }
+        if npc.plan.is_some() {
+            continue;
+        }
+
+        let asleep = matches!(npc.state, NPCState::Sleeping);
+        // hunger keeps people awake so they can still look for food
+        let starving = Hunger::of(character.saturation) == Hunger::Starving;
+        let state = if activity == Activity::Sleep && starving {
+            NPCState::Idle
+        } else {
+            activity.state(&npc, transform.translation.xy(), &regions)
+        };
+        let sleeping = matches!(state, NPCState::Sleeping);
+        if !(started || matches!(npc.state, NPCState::Idle) || (asleep && !sleeping)) {
+            continue;
+        }
+        if asleep && !sleeping {
+            character
+                .actions
+                .push(Action::Emote("wakes up".to_string()));
+        } else if sleeping && !asleep {
+            character
+                .actions
+                .push(Action::Emote("goes to sleep".to_string()));
+        }
+        npc.state = state;
+    }
+}
@@ -0,0 +1,162 @@
+use serde::Deserialize;
+
+/// What an NPC said and did, recovered from a raw model reply.
+#[derive(Debug, Default, PartialEq)]
+pub struct NpcResponse {
+    pub speech: Option<String>,
+    pub emotes: Vec<String>,
+    /// Arguments in the same shape as the `set_task` tool, if the reply asked for a task change.
+    pub task_arguments: Option<serde_json::Value>,
+    /// Arguments in the same shape as the `set_plan` tool, if the reply made a plan.
+    pub plan_arguments: Option<serde_json::Value>,
+}
+
+impl NpcResponse {
+    pub fn is_empty(&self) -> bool {
+        self.speech.is_none()
+            && self.emotes.is_empty()
+            && self.task_arguments.is_none()
+            && self.plan_arguments.is_none()
+    }
+}
+
+/// Shape requested from the model when structured output is enabled.
+#[derive(Deserialize)]
+struct StructuredResponse {
+    #[serde(default)]
+    speech: Option<String>,
+    #[serde(default)]
+    emote: Option<String>,
+    #[serde(default)]
+    task: Option<String>,
+    #[serde(default)]
+    destination: Option<String>,
+    #[serde(default)]
+    plan: Option<serde_json::Value>,
+}
+
+/// Parses a reply from `speaker`, accepting JSON objects as well as the common
+/// ways models format dialog (`Theo:"Hi"`, `**Theo**: Hi`, `*sighs* Hi`, ...).
+/// Once the model starts writing the lines of another of the known `characters`, the rest is
+/// dropped. Any other text before a colon, as in `Listen: the harvest is late`, is speech.
+pub fn parse_npc_response(content: &str, speaker: &str, characters: &[String]) -> NpcResponse {
+    let content = strip_code_fence(content.trim());
+    if content.starts_with('{') {
+        if let Ok(structured) = serde_json::from_str::<StructuredResponse>(content) {
+            return from_structured(structured, speaker);
+        }
+    }
+
+    let mut response = NpcResponse::default();
+    let mut speech_parts = vec![];
+    for line in content.lines() {
+        let line = line.replace("**", "").replace("__", "");
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let body = match split_speaker_tag(line) {
+            Some((tag, body)) if tag.eq_ignore_ascii_case(speaker) => body,
+            // the model started writing someone else's lines
+            Some((tag, _))
+                if characters
+                    .iter()
+                    .any(|character| tag.eq_ignore_ascii_case(character)) =>
+            {
+                break
+            }
+            _ => line,
+        };
+        let (speech, emotes) = extract_emotes(body);
+        response.emotes.extend(emotes);
+        if let Some(speech) = clean_speech(&speech) {
+            speech_parts.push(speech);
+        }
+    }
+    if !speech_parts.is_empty() {
+        response.speech = Some(speech_parts.join(" "));
+    }
+    response
+}
+
+fn from_structured(structured: StructuredResponse, speaker: &str) -> NpcResponse {
+    let mut response = NpcResponse::default();
+    if let Some(speech) = structured.speech {
+        // models sometimes still prefix the name inside the field
+        let speech = match split_speaker_tag(speech.trim()) {
+            Some((tag, body)) if tag.eq_ignore_ascii_case(speaker) => body.to_string(),
+            _ => speech,
+        };
+        let (speech, emotes) = extract_emotes(&speech);
+        response.emotes.extend(emotes);
+        response.speech = clean_speech(&speech);
+    }
+    if let Some(emote) = structured.emote.as_deref().and_then(clean_emote) {
+        response.emotes.push(emote);
+    }
+    if let Some(task) = structured.task {
+        response.task_arguments = Some(serde_json::json!({
+            "task": task,
+            "destination": structured.destination,
+        }));
+    }
+    response.plan_arguments = structured.plan;
+    response
+}
+
+fn strip_code_fence(content: &str) -> &str {
+    let Some(inner) = content.strip_prefix("```") else {
+        return content;
+    };
+    let inner = inner.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    inner.strip_suffix("```").unwrap_or(inner).trim()
+}
+
+/// Splits `Name: text` into its parts if the text before the colon looks like a name.
+fn split_speaker_tag(line: &str) -> Option<(&str, &str)> {
+    let (tag, body) = line.split_once(':')?;
+    let tag = tag.trim_matches(|c: char| c.is_whitespace() || matches!(c, '*' | '_' | '"'));
+    let words = tag.split_whitespace().collect::<Vec<_>>();
+    let looks_like_name = !words.is_empty()
+        && words.len() <= 3
+        && words.iter().all(|word| {
+            word.starts_with(|c: char| c.is_uppercase())
+                && word
+                    .chars()
+                    .all(|c| c.is_alphabetic() || c == '\'' || c == '-')
+        });
+    looks_like_name.then_some((tag, body))
+}
+
+/// Pulls `*stage directions*` out of a line, returning the remaining speech and the emotes.
+fn extract_emotes(text: &str) -> (String, Vec<String>) {
+    let mut speech = String::new();
+    let mut emotes = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('*') {
+        let Some(len) = rest[start + 1..].find('*') else {
+            break;
+        };
+        speech.push_str(&rest[..start]);
+        speech.push(' ');
+        if let Some(emote) = clean_emote(&rest[start + 1..start + 1 + len]) {
+            emotes.push(emote);
+        }
+        rest = &rest[start + len + 2..];
+    }
+    speech.push_str(rest);
+    (speech, emotes)
+}
+
+fn clean_emote(emote: &str) -> Option<String> {
+    let emote = emote.trim().trim_end_matches('.').trim();
+    (!emote.is_empty()).then(|| emote.to_string())
+}
+
+fn clean_speech(speech: &str) -> Option<String> {
+    let speech = speech.split_whitespace().collect::<Vec<_>>().join(" ");
+    let speech = speech
+        .trim_matches(|c: char| matches!(c, '"' | '“' | '”') || c.is_whitespace())
+        .to_string();
+    (!speech.is_empty()).then_some(speech)
+}
@@ -0,0 +1,344 @@
+use std::{collections::HashMap, fs};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::Deserialize;
+
+use crate::{clock::GameClock, death::Dead, Action, Character, Region, NPC};
+
+pub const STORYLINES_PATH: &str = "assets/storylines.json";
+
+/// How often storylines are checked against the world, in seconds.
+const EVALUATION_INTERVAL: f32 = 5.0;
+/// How many recent lines of speech per character predicates can look at.
+const SPEECH_MEMORY: usize = 20;
+const DEFAULT_NEAR_DISTANCE: f32 = 300.0;
+
+/// A condition on the world that authored storylines use to detect progress.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Predicate {
+    InRegion {
+        character: String,
+        region: String,
+    },
+    Near {
+        character: String,
+        other: String,
+        #[serde(default)]
+        distance: Option<f32>,
+    },
+    /// `task` is one of `idle`, `farming`, `traveling`, `sleeping` or the name of an activity in
+    /// `assets/behaviours.json`.
+    Task {
+        character: String,
+        task: String,
+    },
+    /// The character recently said something containing any of the words.
+    Said {
+        character: String,
+        any_of: Vec<String>,
+    },
+    Dead {
+        character: String,
+    },
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    fn evaluate(&self, world: &WorldSnapshot) -> bool {
+        match self {
+            Predicate::InRegion { character, region } => {
+                let Some(position) = world.position(character) else {
+                    return false;
+                };
+                world
+                    .regions
+                    .iter()
+                    .any(|(name, range)| name == region && range.contains(position))
+            }
+            Predicate::Near {
+                character,
+                other,
+                distance,
+            } => match (world.position(character), world.position(other)) {
+                (Some(a), Some(b)) => a.distance(b) < distance.unwrap_or(DEFAULT_NEAR_DISTANCE),
+                _ => false,
+            },
+            Predicate::Task { character, task } => world
+                .characters
+                .get(character)
+                .and_then(|snapshot| snapshot.task.as_deref())
+                .is_some_and(|current| current == task),
+            Predicate::Said { character, any_of } => {
+                world.recent_speech.get(character).is_some_and(|lines| {
+                    lines.iter().any(|line| {
+                        let line = line.to_lowercase();
+                        any_of
+                            .iter()
+                            .any(|word| line.contains(&word.to_lowercase()))
+                    })
+                })
+            }
+            Predicate::Dead { character } => world
+                .characters
+                .get(character)
+                .is_some_and(|snapshot| snapshot.dead),
+            Predicate::All(predicates) => predicates.iter().all(|p| p.evaluate(world)),
+            Predicate::Any(predicates) => predicates.iter().any(|p| p.evaluate(world)),
+            Predicate::Not(predicate) => !predicate.evaluate(world),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Milestone {
+    /// Journal entry, also remembered by the participants when reached.
+    pub description: String,
+    pub when: Predicate,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Resolution {
+    pub name: String,
+    /// Remembered by every NPC in the village when the storyline resolves this way.
+    pub description: String,
+    pub when: Predicate,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct StorylineDefinition {
+    pub title: String,
+    pub participants: Vec<String>,
+    pub stakes: String,
+    #[serde(default)]
+    pub milestones: Vec<Milestone>,
+    pub resolutions: Vec<Resolution>,
+}
+
+pub struct Storyline {
+    pub definition: StorylineDefinition,
+    /// Indices into `definition.milestones`, in the order they were reached.
+    pub reached_milestones: Vec<usize>,
+    pub resolution: Option<usize>,
+}
+
+impl Storyline {
+    pub fn status(&self) -> &'static str {
+        if self.resolution.is_some() {
+            "Resolved"
+        } else if !self.reached_milestones.is_empty() {
+            "Progressed"
+        } else {
+            "Unresolved"
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Storylines {
+    pub storylines: Vec<Storyline>,
+    recent_speech: HashMap<String, Vec<String>>,
+    evaluation_timer: f32,
+}
+
+impl Storylines {
+    /// Loads the conflicts authored in `assets/storylines.json`.
+    pub fn load() -> Self {
+        let definitions = fs::read_to_string(STORYLINES_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                serde_json::from_str::<Vec<StorylineDefinition>>(&source).map_err(|e| e.to_string())
+            });
+        let definitions = definitions.unwrap_or_else(|e| {
+            println!("Could not load {}: {}", STORYLINES_PATH, e);
+            vec![]
+        });
+        Storylines {
+            storylines: definitions
+                .into_iter()
+                .map(|definition| Storyline {
+                    definition,
+                    reached_milestones: vec![],
+                    resolution: None,
+                })
+                .collect(),
+            ..default()
+        }
+    }
+}
+
+/// Sent when a storyline reaches a milestone or resolves.
+#[derive(Event)]
+pub struct StorylineEvent {
+    pub description: String,
+    /// NPCs that should remember the event, `None` for the whole village.
+    pub witnesses: Option<Vec<String>>,
+}
+
+struct CharacterSnapshot {
+    position: Vec2,
+    task: Option<String>,
+    dead: bool,
+}
+
+struct WorldSnapshot<'a> {
+    characters: HashMap<String, CharacterSnapshot>,
+    regions: Vec<(String, Rect)>,
+    recent_speech: &'a HashMap<String, Vec<String>>,
+}
+
+impl WorldSnapshot<'_> {
+    fn position(&self, character: &str) -> Option<Vec2> {
+        self.characters
+            .get(character)
+            .map(|snapshot| snapshot.position)
+    }
+}
+
+/// Remembers what everyone said recently so `said` predicates can match it.
+pub fn record_speech(mut storylines: ResMut<Storylines>, characters: Query<&Character>) {
+    for character in &characters {
+        for action in &character.actions {
+            if let Action::Talk(speech) = action {
+                let lines = storylines
+                    .recent_speech
+                    .entry(character.name.clone())
+                    .or_default();
+                lines.push(speech.clone());
+                if lines.len() > SPEECH_MEMORY {
+                    lines.remove(0);
+                }
+            }
+        }
+    }
+}
+
+pub fn evaluate_storylines(
+    time: Res<Time>,
+    mut storylines: ResMut<Storylines>,
+    characters: Query<(&Character, &Transform, Option<&NPC>, Has<Dead>)>,
+    regions: Query<&Region>,
+    mut events: EventWriter<StorylineEvent>,
+) {
+    storylines.evaluation_timer -= time.delta_seconds();
+    if storylines.evaluation_timer > 0.0 {
+        return;
+    }
+    storylines.evaluation_timer = EVALUATION_INTERVAL;
+
+    let Storylines {
+        storylines,
+        recent_speech,
+        ..
+    } = storylines.as_mut();
+    let world = WorldSnapshot {
+        characters: characters
+            .iter()
+            .map(|(character, transform, npc, dead)| {
+                let task = npc.map(|npc| npc.state.name().to_string());
+                (
+                    character.name.clone(),
+                    CharacterSnapshot {
+                        position: transform.translation.xy(),
+                        task,
+                        dead,
+                    },
+                )
+            })
+            .collect(),
+        regions: regions
+            .iter()
+            .map(|region| (region.name.clone(), region.range))
+            .collect(),
+        recent_speech,
+    };
+
+    for storyline in storylines.iter_mut() {
+        if storyline.resolution.is_some() {
+            continue;
+        }
+        let definition = &storyline.definition;
+        for (index, milestone) in definition.milestones.iter().enumerate() {
+            if !storyline.reached_milestones.contains(&index) && milestone.when.evaluate(&world) {
+                println!(
+                    "Storyline {} progressed: {}",
+                    definition.title, milestone.description
+                );
+                storyline.reached_milestones.push(index);
+                events.send(StorylineEvent {
+                    description: milestone.description.clone(),
+                    witnesses: Some(definition.participants.clone()),
+                });
+            }
+        }
+        if let Some(index) = definition
+            .resolutions
+            .iter()
+            .position(|resolution| resolution.when.evaluate(&world))
+        {
+            let resolution = &definition.resolutions[index];
+            println!(
+                "Storyline {} resolved: {}",
+                definition.title, resolution.name
+            );
+            storyline.resolution = Some(index);
+            events.send(StorylineEvent {
+                description: resolution.description.clone(),
+                witnesses: None,
+            });
+        }
+    }
+}
+
+/// Feeds storyline events back into the memory of living NPCs.
+pub fn remember_storyline_events(
+    clock: Res<GameClock>,
+    mut events: EventReader<StorylineEvent>,
+    mut npcs: Query<(&mut NPC, &Character), Without<Dead>>,
+) {
+    for event in events.read() {
+        for (mut npc, character) in &mut npcs {
+            let witnessed = event
+                .witnesses
+                .as_ref()
+                .is_none_or(|witnesses| witnesses.contains(&character.name));
+            if witnessed {
+                npc.remember(
+                    clock.now,
+                    character.name.clone(),
+                    Action::Event(event.description.clone()),
+                );
+                // news may change what the NPC set out to do
+                if npc.plan.is_some() {
+                    npc.replan = true;
+                    npc.chat_cooldown = 0.0;
+                }
+            }
+        }
+    }
+}
+
+pub fn journal_system(mut contexts: EguiContexts, storylines: Res<Storylines>) {
+    egui::Window::new("Journal")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for storyline in &storylines.storylines {
+                let definition = &storyline.definition;
+                ui.collapsing(
+                    format!("{} ({})", definition.title, storyline.status()),
+                    |ui| {
+                        ui.label(&definition.stakes);
+                        for &index in &storyline.reached_milestones {
+                            ui.label(format!("- {}", definition.milestones[index].description));
+                        }
+                        if let Some(index) = storyline.resolution {
+                            ui.label(format!("- {}", definition.resolutions[index].description));
+                        }
+                    },
+                );
+            }
+        });
+}
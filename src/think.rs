@@ -0,0 +1,217 @@
+//! Decides which NPC prompts go to the dialog backend and when, so only a few requests are in
+//! flight at once and the NPCs the player is dealing with get answers first. Each prompt keeps
+//! the situation it was built in, so a reply that no longer fits can be thrown away.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    death::Dead,
+    dialog::{DialogCancel, DialogPrompt, DialogReply},
+    npc::{NPCState, NpcAiSettings},
+    NPC,
+};
+
+/// How urgently an NPC needs an answer, lowest first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ThinkPriority {
+    Background,
+    /// Close enough to the player to be seen or heard.
+    NearPlayer,
+    /// The player spoke to the NPC or said their name.
+    Addressed,
+}
+
+/// The parts of an NPC's situation a prompt was built from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ThoughtContext {
+    pub state: NPCState,
+    /// Who the NPC could see when prompted, and so might be talking to.
+    pub addressees: Vec<String>,
+}
+
+/// Why a reply no longer fits the NPC's situation.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Staleness {
+    /// The NPC is doing something else now, so it should think again.
+    TaskChanged,
+    /// Everyone the NPC could see when it was prompted has gone out of earshot.
+    AddresseesLeft,
+}
+
+impl ThoughtContext {
+    /// Whether a reply to a prompt built in this context is out of date now that the NPC is in
+    /// `state` and can hear the characters in `audible`.
+    pub fn staleness(&self, state: &NPCState, audible: &[String]) -> Option<Staleness> {
+        if self.state != *state {
+            Some(Staleness::TaskChanged)
+        } else if !self.addressees.is_empty()
+            && !self
+                .addressees
+                .iter()
+                .any(|addressee| audible.contains(addressee))
+        {
+            Some(Staleness::AddresseesLeft)
+        } else {
+            None
+        }
+    }
+}
+
+/// A prompt that was sent and not answered yet.
+#[derive(Clone, Debug)]
+pub struct Thought {
+    pub request: u64,
+    pub priority: ThinkPriority,
+    pub context: ThoughtContext,
+    /// Elapsed seconds when the prompt was sent.
+    pub sent_at: f32,
+}
+
+struct QueuedThought {
+    prompt: DialogPrompt,
+    priority: ThinkPriority,
+    context: ThoughtContext,
+    /// Elapsed seconds when the prompt was queued.
+    queued_at: f32,
+}
+
+/// Prompts waiting for a free request slot and the NPCs with a request in flight.
+#[derive(Resource, Default)]
+pub struct ThinkScheduler {
+    queue: Vec<QueuedThought>,
+    in_flight: HashMap<Entity, Thought>,
+    next_request: u64,
+}
+
+impl ThinkScheduler {
+    /// Queues `prompt`, built in `context`, replacing any prompt the NPC still had waiting. An
+    /// addressed NPC's newer prompt also replaces the request in flight, which the backend then
+    /// drops.
+    pub fn request(
+        &mut self,
+        prompt: DialogPrompt,
+        priority: ThinkPriority,
+        context: ThoughtContext,
+        now: f32,
+    ) {
+        self.queue
+            .retain(|thought| thought.prompt.npc != prompt.npc);
+        self.queue.push(QueuedThought {
+            prompt,
+            priority,
+            context,
+            queued_at: now,
+        });
+    }
+
+    /// Frees the request slot `reply` answers, returning what the prompt was built from. A reply
+    /// to a request that was since replaced or cancelled gets `None`.
+    pub fn finish(&mut self, reply: &DialogReply) -> Option<Thought> {
+        if self.in_flight.get(&reply.npc)?.request != reply.request {
+            return None;
+        }
+        self.in_flight.remove(&reply.npc)
+    }
+
+    pub fn is_thinking(&self, npc: Entity) -> bool {
+        self.in_flight.contains_key(&npc)
+            || self.queue.iter().any(|thought| thought.prompt.npc == npc)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// The waiting prompt to send next: the most urgent, then the longest waiting. A prompt needs
+    /// a free slot unless it replaces its NPC's request in flight, which only an addressed NPC's
+    /// prompt may do.
+    fn next(&mut self, max_in_flight: usize) -> Option<QueuedThought> {
+        let has_free_slot = self.in_flight.len() < max_in_flight;
+        let index = self
+            .queue
+            .iter()
+            .enumerate()
+            .filter(
+                |(_, thought)| match self.in_flight.get(&thought.prompt.npc) {
+                    Some(in_flight) => {
+                        thought.priority == ThinkPriority::Addressed
+                            && in_flight.priority != ThinkPriority::Addressed
+                    }
+                    None => has_free_slot,
+                },
+            )
+            .max_by(|(a_index, a), (b_index, b)| {
+                a.priority
+                    .cmp(&b.priority)
+                    .then(b.queued_at.total_cmp(&a.queued_at))
+                    .then(b_index.cmp(a_index))
+            })
+            .map(|(index, _)| index)?;
+        Some(self.queue.remove(index))
+    }
+}
+
+/// Drops prompts and requests that no longer make sense, including requests that went unanswered
+/// for longer than `stale_after`, then sends the most urgent prompts while there are free request
+/// slots.
+pub fn dispatch_thoughts(
+    time: Res<Time>,
+    settings: Res<NpcAiSettings>,
+    mut scheduler: ResMut<ThinkScheduler>,
+    npcs: Query<(&NPC, Has<Dead>)>,
+    mut prompts: EventWriter<DialogPrompt>,
+    mut cancels: EventWriter<DialogCancel>,
+) {
+    let now = time.elapsed_seconds();
+    // dead, sleeping or despawned NPCs have nothing to say
+    let is_awake = |npc: Entity| {
+        npcs.get(npc)
+            .is_ok_and(|(npc, dead)| !dead && !matches!(npc.state, NPCState::Sleeping))
+    };
+    scheduler.queue.retain(|thought| {
+        let fresh = now - thought.queued_at <= settings.stale_after;
+        if !fresh {
+            println!(
+                "Dropping a stale prompt after {:.0}s",
+                now - thought.queued_at
+            );
+        }
+        fresh && is_awake(thought.prompt.npc)
+    });
+    scheduler.in_flight.retain(|&npc, thought| {
+        // a hung request would hold its slot for good
+        let answered_in_time = now - thought.sent_at <= settings.stale_after;
+        if !answered_in_time {
+            println!(
+                "Giving up on a request after {:.0}s without an answer",
+                now - thought.sent_at
+            );
+        }
+        let keep = answered_in_time && is_awake(npc);
+        if !keep {
+            cancels.send(DialogCancel { npc });
+        }
+        keep
+    });
+
+    while let Some(mut thought) = scheduler.next(settings.max_concurrent_requests) {
+        scheduler.next_request += 1;
+        thought.prompt.request = scheduler.next_request;
+        scheduler.in_flight.insert(
+            thought.prompt.npc,
+            Thought {
+                request: thought.prompt.request,
+                priority: thought.priority,
+                context: thought.context,
+                sent_at: now,
+            },
+        );
+        prompts.send(thought.prompt);
+    }
+}
@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+
+use crate::{
+    clock::{night_overlay_bundle, NightOverlay},
+    fill_character, fill_rect_with_plants,
+    interaction::{spawn_doors, Bed},
+    navigation::NavigationGrid,
+    perception::Perception,
+    Character, FarmingSettings, NPCState, Player, Region, StartPos, NPC,
+};
+
+/// Spawns the village: the camera, the four farms, their houses and the villagers.
+pub fn spawn_village(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    grid: Res<NavigationGrid>,
+    farming: Res<FarmingSettings>,
+) {
+    // Camera
+    // Space between the two ears
+    let gap = 200.0;
+    let listener = SpatialListener::new(gap);
+    commands
+        .spawn(Camera2dBundle {
+            projection: OrthographicProjection {
+                far: 1000.,
+                near: -1000.,
+                scaling_mode: bevy::render::camera::ScalingMode::WindowSize(1.7),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert((SpatialBundle::default(), listener.clone()))
+        .with_children(|parent| {
+            parent.spawn((NightOverlay, night_overlay_bundle()));
+        });
+
+    // Background
+    let background_scale = 2.0;
+    commands.spawn(SpriteBundle {
+        texture: asset_server.load("textures/background_v2.png"),
+        transform: Transform {
+            translation: Vec3::new(-1500.0, 1500.0, -1.0),
+            scale: Vec3::new(background_scale, background_scale, 0.0),
+            ..default()
+        },
+        ..Default::default()
+    });
+
+    // Regions & Plants
+    let theo_farm_rect = Rect::new(-760.0, 0.0, 320.0, 720.0);
+    commands.spawn((Region {
+        name: "Theo's Family Farm".to_string(),
+        range: theo_farm_rect,
+    },));
+    fill_rect_with_plants(&mut commands, &asset_server, &farming, theo_farm_rect);
+
+    let bill_farm_rect = Rect::new(-2520.0, 0.0, -1020.0, 1740.0);
+    commands.spawn((Region {
+        name: "Bill's Farm".to_string(),
+        range: bill_farm_rect,
+    },));
+    fill_rect_with_plants(&mut commands, &asset_server, &farming, bill_farm_rect);
+
+    let steve_farm_rect = Rect::new(-800.0, 1840.0, 300.0, 2600.0);
+    commands.spawn((Region {
+        name: "Steve's Farm".to_string(),
+        range: steve_farm_rect,
+    },));
+    fill_rect_with_plants(&mut commands, &asset_server, &farming, steve_farm_rect);
+
+    let jacob_farm_rect = Rect::new(-3260.0, 2020.0, -2240.0, 2530.0);
+    commands.spawn((Region {
+        name: "Jacob's Farm".to_string(),
+        range: jacob_farm_rect,
+    },));
+    fill_rect_with_plants(&mut commands, &asset_server, &farming, jacob_farm_rect);
+
+    spawn_doors(&mut commands, &grid);
+
+    // Player and their bed
+    commands.spawn((
+        Bed,
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.6, 0.45, 0.3),
+                custom_size: Some(Vec2::new(50.0, 80.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(200.0, -200.0, -0.5),
+            ..default()
+        },
+    ));
+    commands
+        .spawn((
+            StartPos(Vec2::new(0.0, 0.0)),
+            Character {
+                name: "James".to_string(),
+                ..Default::default()
+            },
+            Player::default(),
+        ))
+        .add(fill_character);
+
+    // NPCs
+    commands.spawn((
+        StartPos(Vec2::new(-100.0, 80.0)),
+        Character {
+            name: "Theo".to_string(),
+            ..Default::default()
+        },
+        // getting old, and his hearing with him
+        Perception {
+            hearing_radius: 400.0,
+            ..Default::default()
+        },
+        NPC {
+            backstory: "You are Theo. A stern 16th century Farmer living in a small village in medieval europe. You live with your wife Jessica and son Jeff on your own small patch of land. You know your land is small but it has been owned by centuries by your family. Jeff wants to start working on your neighbor Bill's land because it is much bigger, but you want your family to continue farming your historical land. You also know you are getting old and tired and will soon need Jeff's help, especially if you have to support Jessica without help. ".to_string(),
+            chat_cooldown: 10.0,
+            state: NPCState::Farming,
+            home: "Theo's Family Farm".to_string(),
+            ..Default::default()
+        },
+    )).add(fill_character);
+
+    commands.spawn((
+        StartPos(Vec2::new(100.0, 50.0)),
+        Character {
+            name: "Jeff".to_string(),
+            ..Default::default()
+        },
+        NPC {
+            backstory: "You are Jeff. A young 16th century Farmer living in a small village in medival europe. You currently live with your parents Theo and Jessica on their small farm. However you know your land is small and will have trouble feeding all three of you so you'd like to move to your neighbor Bill's land in order to stop burdening your family. You've brought this up before, but Theo objects due to heritage reasons, whereas you think eating is more important than tradition. ".to_string(),
+            chat_cooldown: 3.0,
+            state: NPCState::Idle,
+            home: "Theo's Family Farm".to_string(),
+            ..Default::default()
+        },
+    )).add(fill_character);
+
+    commands.spawn((
+        StartPos(bill_farm_rect.center()),
+        Character {
+            name: "Bill".to_string(),
+            ..Default::default()
+        },
+        NPC {
+            backstory: "You are Bill. A cunning 16th century Farmer living in a small village in medival europe. You live on a farm you've been growing in size for decades. You hope to recruit a village boy Jeff from a nearby farm to help you farm your land, as it currently takes up most of your time. ".to_string(),
+            chat_cooldown: 25.0,
+            state: NPCState::Farming,
+            home: "Bill's Farm".to_string(),
+            ..Default::default()
+        },
+    )).add(fill_character);
+
+    commands.spawn((
+        StartPos(jacob_farm_rect.center()),
+        Character {
+            name: "Jacob".to_string(),
+            ..Default::default()
+        },
+        NPC {
+            backstory: "You are Jacob. A reclusive 16th century Farmer living in a small village in medival europe. You live on a small farm by yourself, and try to stay out of everyone's buissiness in the hopes they'll stay out of yours. ".to_string(),
+            chat_cooldown: 42.0,
+            state: NPCState::Farming,
+            home: "Jacob's Farm".to_string(),
+            ..Default::default()
+        },
+    )).add(fill_character);
+
+    commands.spawn((
+        StartPos(steve_farm_rect.center()),
+        Character {
+            name: "Steve".to_string(),
+            ..Default::default()
+        },
+        NPC {
+            backstory: "You are Steve. An outgoing 16th century Farmer living in a small village in medival europe. You live on a small farm by yourself, but try to bring the community of the village together by trying to organize events and going over to people's houses. You are worried about Jacob as he doesn't socialize much, which can't be good for him. ".to_string(),
+            chat_cooldown: 60.0,
+            state: NPCState::Farming,
+            home: "Steve's Farm".to_string(),
+            ..Default::default()
+        },
+    )).add(fill_character);
+}
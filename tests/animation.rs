@@ -0,0 +1,21 @@
+//! Sprite sheets for walk animations.
+
+use std::{env, fs};
+
+use bevy::prelude::*;
+use bevy_rpg::animation::{sheet_layout, sheet_path};
+
+#[test]
+fn sheet_frames_are_sized_from_the_sheet() {
+    // only the size in the PNG header is read
+    let path = env::temp_dir().join("bevy_rpg_walk_sheet.png");
+    let mut header = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    header.extend(924u32.to_be_bytes());
+    header.extend(956u32.to_be_bytes());
+    fs::write(&path, header).unwrap();
+
+    let layout = sheet_layout(path.to_str().unwrap()).expect("a layout");
+    assert_eq!(layout.len(), 16);
+    assert_eq!(layout.textures[0].size(), Vec2::new(231.0, 239.0));
+    assert!(sheet_layout(&sheet_path("Nobody")).is_none());
+}
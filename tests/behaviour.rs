@@ -0,0 +1,209 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_rpg::{
+    behaviour::Behaviours,
+    navigation::{NavigationGrid, Obstacle, ObstacleKind},
+    Action, Character, Item, NPCState, Plant, Region, NPC,
+};
+use common::{run_for, spawn_character, spawn_npc, test_app, tool_call, ScriptedDialog};
+
+fn state(app: &App, npc: Entity) -> NPCState {
+    app.world.get::<NPC>(npc).unwrap().state.clone()
+}
+
+fn position(app: &App, entity: Entity) -> Vec2 {
+    app.world.get::<Transform>(entity).unwrap().translation.xy()
+}
+
+fn with_behaviours(app: &mut App, json: &str) {
+    app.insert_resource(Behaviours::from_json(json).unwrap());
+}
+
+#[test]
+fn authored_activities_run_their_tree_then_go_idle() {
+    let mut app = test_app();
+    with_behaviours(
+        &mut app,
+        r#"{"stretching": {"node": "sequence", "children": [
+            {"node": "talk", "text": "Oof, my back."},
+            {"node": "wait", "seconds": 2}
+        ]}}"#,
+    );
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Activity("stretching".to_string()),
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 1.0);
+    assert_eq!(
+        state(&app, npc),
+        NPCState::Activity("stretching".to_string())
+    );
+    let history = &app.world.get::<NPC>(npc).unwrap().history;
+    assert!(history
+        .iter()
+        .any(|memory| memory.action == Action::Talk("Oof, my back.".to_string())));
+
+    run_for(&mut app, 1.5);
+    assert_eq!(state(&app, npc), NPCState::Idle);
+}
+
+#[test]
+fn unknown_activities_are_given_up() {
+    let mut app = test_app();
+    app.world
+        .resource_mut::<ScriptedDialog>()
+        .replies
+        .push_back(tool_call("set_task", r#"{"task": "juggling"}"#));
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Farming,
+            chat_cooldown: 0.0,
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 0.5);
+
+    assert_eq!(state(&app, npc), NPCState::Idle);
+}
+
+#[test]
+fn followers_keep_up_and_cowards_flee() {
+    let mut app = test_app();
+    with_behaviours(
+        &mut app,
+        r#"{
+            "tagging_along": {"node": "follow", "target": {"person": "Bob"}, "distance": 80},
+            "hiding": {"node": "flee", "target": {"person": "Bob"}, "distance": 300}
+        }"#,
+    );
+    let bob = spawn_character(
+        &mut app,
+        Character {
+            name: "Bob".to_string(),
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+    let follower = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Activity("tagging_along".to_string()),
+            ..default()
+        },
+        Vec2::new(400.0, 0.0),
+    );
+    let coward = spawn_npc(
+        &mut app,
+        "Cat",
+        NPC {
+            state: NPCState::Activity("hiding".to_string()),
+            ..default()
+        },
+        Vec2::new(0.0, 100.0),
+    );
+
+    run_for(&mut app, 8.0);
+
+    let bob = position(&app, bob);
+    assert!(position(&app, follower).distance(bob) <= 100.0);
+    assert_eq!(
+        state(&app, follower),
+        NPCState::Activity("tagging_along".to_string())
+    );
+    assert!(position(&app, coward).distance(bob) >= 300.0);
+    assert_eq!(state(&app, coward), NPCState::Idle);
+}
+
+#[test]
+fn the_model_is_offered_every_authored_activity() {
+    let behaviours = Behaviours::from_json(
+        r#"{"sleeping": {"node": "wait", "seconds": 1}, "taking_a_break": {"node": "eat"}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        behaviours.task_names(),
+        ["farming", "idle", "taking_a_break", "traveling"]
+    );
+}
+
+#[test]
+fn harvesters_turn_to_plants_behind_them() {
+    let mut app = test_app();
+    app.world.spawn(Region {
+        name: "Field".to_string(),
+        range: Rect::new(-200.0, -200.0, 200.0, 200.0),
+    });
+    // NPCs start out facing down, away from the plant
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Farming,
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+    let plant = app
+        .world
+        .spawn((
+            Plant { growth: 1.0 },
+            Transform::from_xyz(0.0, 20.0, 0.0),
+            Handle::<Image>::default(),
+        ))
+        .id();
+
+    run_for(&mut app, 0.5);
+    assert!(!app.world.get::<Plant>(plant).unwrap().is_grown());
+    let items = &app.world.get::<Character>(npc).unwrap().items;
+    assert!(items.iter().any(|(item, _)| *item == Item::Plant));
+}
+
+#[test]
+fn walkers_give_up_on_targets_inside_buildings() {
+    let mut app = test_app();
+    app.insert_resource(NavigationGrid::new(
+        Rect::new(-1000.0, -1000.0, 1000.0, 1000.0),
+        vec![Obstacle {
+            kind: ObstacleKind::Building,
+            name: None,
+            rect: [100.0, -100.0, 300.0, 100.0],
+        }],
+    ));
+    app.world.spawn(Region {
+        name: "Cellar".to_string(),
+        range: Rect::new(180.0, -20.0, 220.0, 20.0),
+    });
+    with_behaviours(
+        &mut app,
+        r#"{"fetching": {"node": "move_to", "target": {"region": "Cellar"}}}"#,
+    );
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Activity("fetching".to_string()),
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 5.0);
+
+    assert_eq!(state(&app, npc), NPCState::Idle);
+    // it got as close as it could, just outside the walls
+    let building = Rect::new(100.0, -100.0, 300.0, 100.0);
+    assert!(!building.contains(position(&app, npc)));
+}
@@ -0,0 +1,121 @@
+//! A headless app with the gameplay plugins, a scripted dialog backend and a manual clock.
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{input::InputPlugin, prelude::*, time::TimeUpdateStrategy};
+use bevy_rpg::{
+    animation::Facing,
+    dialog::{
+        DialogPrompt, DialogReply, ModelRole, OpenAIFunctionCall, OpenAIMessage, OpenAIToolCall,
+    },
+    navigation::NavigationGrid,
+    perception::Perception,
+    schedule::Schedules,
+    Character, CharacterPlugin, FarmingPlugin, NpcAiPlugin, NpcAiSettings, StartPos, NPC,
+};
+
+/// Length of one `App::update`.
+pub const STEP: Duration = Duration::from_millis(100);
+
+/// Replies handed out in order, one per prompt. Prompts that find the queue empty fail.
+#[derive(Resource, Default)]
+pub struct ScriptedDialog {
+    pub replies: VecDeque<OpenAIMessage>,
+    /// Names of the NPCs that were prompted, in order.
+    pub prompted: Vec<String>,
+    /// What each prompt was for, in order.
+    pub roles: Vec<ModelRole>,
+}
+
+fn answer_prompts(
+    mut script: ResMut<ScriptedDialog>,
+    mut prompts: EventReader<DialogPrompt>,
+    mut replies: EventWriter<DialogReply>,
+    characters: Query<&Character>,
+) {
+    for prompt in prompts.read() {
+        if let Ok(character) = characters.get(prompt.npc) {
+            script.prompted.push(character.name.clone());
+        }
+        script.roles.push(prompt.role);
+        replies.send(DialogReply {
+            npc: prompt.npc,
+            message: script.replies.pop_front(),
+            role: prompt.role,
+            variant: prompt.variant.clone(),
+            request: prompt.request,
+        });
+    }
+}
+
+/// An open 2000x2000 map around the origin, without schedules, whose NPCs only think when their
+/// own `chat_cooldown` runs out.
+pub fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default(), InputPlugin))
+        // plants swap their textures as they grow
+        .init_asset::<Image>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(STEP))
+        .insert_resource(NavigationGrid::new(
+            Rect::new(-1000.0, -1000.0, 1000.0, 1000.0),
+            vec![],
+        ))
+        .insert_resource(Schedules::default())
+        .insert_resource(NpcAiSettings {
+            chat_cooldown: 1000.0,
+            ..default()
+        })
+        .add_plugins((FarmingPlugin, CharacterPlugin, NpcAiPlugin))
+        .init_resource::<ScriptedDialog>()
+        .add_systems(Update, answer_prompts);
+    app
+}
+
+pub fn run_for(app: &mut App, seconds: f32) {
+    let steps = (seconds / STEP.as_secs_f32()).ceil() as u32;
+    for _ in 0..steps {
+        app.update();
+    }
+}
+
+/// Spawns a character without sprites, with everything the gameplay systems look for.
+pub fn spawn_character(app: &mut App, character: Character, position: Vec2) -> Entity {
+    app.world
+        .spawn((
+            character,
+            StartPos(position),
+            Transform::from_translation(position.extend(0.0)),
+            Facing::new(position),
+            Perception::default(),
+        ))
+        .id()
+}
+
+pub fn spawn_npc(app: &mut App, name: &str, npc: NPC, position: Vec2) -> Entity {
+    let entity = spawn_character(
+        app,
+        Character {
+            name: name.to_string(),
+            ..default()
+        },
+        position,
+    );
+    app.world.entity_mut(entity).insert(npc);
+    entity
+}
+
+pub fn tool_call(name: &str, arguments: &str) -> OpenAIMessage {
+    OpenAIMessage {
+        role: "assistant".to_string(),
+        content: None,
+        name: None,
+        tool_calls: Some(vec![OpenAIToolCall {
+            id: "call_0".to_string(),
+            tool_type: "function".to_string(),
+            function: OpenAIFunctionCall {
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            },
+        }]),
+    }
+}
@@ -0,0 +1,113 @@
+//! Settings layered from a file and command line flags.
+
+use std::{env, fs};
+
+use bevy::prelude::*;
+use bevy_rpg::{
+    config::{ConfigArgs, GameConfig},
+    dialog::ModelRole,
+    CharacterPlugin, CharacterSettings, DialogSettings,
+};
+
+fn args(args: &[&str]) -> ConfigArgs {
+    ConfigArgs::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+}
+
+#[test]
+fn flags_override_the_file_which_overrides_the_defaults() {
+    let path = env::temp_dir().join("bevy_rpg_layered_config.json");
+    fs::write(
+        &path,
+        r#"{"dialog": {"model": "file-model", "max_tokens": 32}, "character": {"speed": 90}}"#,
+    )
+    .unwrap();
+    let config = GameConfig::load(&args(&[
+        "--headless",
+        "--config",
+        path.to_str().unwrap(),
+        "--set",
+        "dialog.model=flag-model",
+        "--set",
+        r#"dialog.stop=[".", "!"]"#,
+    ]))
+    .unwrap();
+    assert_eq!(config.dialog.model, "flag-model");
+    assert_eq!(config.dialog.max_tokens, 32);
+    assert_eq!(config.dialog.stop, vec![".", "!"]);
+    assert_eq!(
+        config.dialog.temperature,
+        DialogSettings::default().temperature
+    );
+    assert_eq!(config.character.speed, 90.0);
+}
+
+#[test]
+fn text_settings_keep_numeric_looking_values_as_text() {
+    let config = GameConfig::load(&args(&["--set", "dialog.model=4"])).unwrap();
+    assert_eq!(config.dialog.model, "4");
+}
+
+#[test]
+fn unknown_and_mistyped_settings_are_rejected() {
+    assert!(GameConfig::load(&args(&["--set", "dialog.modle=gpt-4"])).is_err());
+    assert!(GameConfig::load(&args(&["--set", "character.speed=fast"])).is_err());
+    assert!(ConfigArgs::parse(["--set".to_string(), "speed".to_string()]).is_err());
+}
+
+#[test]
+fn settings_out_of_range_are_rejected() {
+    let error = |setting: &str| GameConfig::load(&args(&["--set", setting])).unwrap_err();
+    assert_eq!(
+        error("farming.plant_spacing=0.5"),
+        "farming.plant_spacing must be >= 1"
+    );
+    assert_eq!(
+        error("npc_ai.max_concurrent_requests=0"),
+        "npc_ai.max_concurrent_requests must be >= 1"
+    );
+    assert_eq!(error("character.speed=-10"), "character.speed must be >= 0");
+    assert_eq!(
+        error("npc_ai.chat_cooldown=-1"),
+        "npc_ai.chat_cooldown must be >= 0"
+    );
+}
+
+#[test]
+fn unknown_flags_are_rejected() {
+    assert!(args(&["--headless"]).headless);
+    for typo in [
+        &["--print-confg"][..],
+        &["--conifg", "x.json"],
+        &["headless"],
+    ] {
+        let typo = typo.iter().map(|arg| arg.to_string());
+        assert!(ConfigArgs::parse(typo).is_err());
+    }
+}
+
+#[test]
+fn plugins_use_the_inserted_settings() {
+    let config = GameConfig::load(&args(&["--set", "character.speed=42"])).unwrap();
+    let mut app = App::new();
+    config.insert(&mut app);
+    app.add_plugins((MinimalPlugins, CharacterPlugin));
+    assert_eq!(app.world.resource::<CharacterSettings>().speed, 42.0);
+}
+
+#[test]
+fn model_profiles_fall_back_on_the_general_dialog_settings() {
+    let config = GameConfig::load(&args(&[
+        "--set",
+        "dialog.model=general-model",
+        "--set",
+        r#"dialog.planning={"model": "planning-model", "endpoint": "http://localhost:1/v1/chat/completions"}"#,
+    ]))
+    .unwrap();
+    let planning = config.dialog.profile(ModelRole::Planning);
+    assert_eq!(planning.model, "planning-model");
+    assert_eq!(planning.endpoint, "http://localhost:1/v1/chat/completions");
+    assert_eq!(planning.max_tokens, config.dialog.max_tokens);
+    let conversation = config.dialog.profile(ModelRole::Conversation);
+    assert_eq!(conversation.model, "general-model");
+    assert_eq!(conversation.endpoint, config.dialog.endpoint);
+}
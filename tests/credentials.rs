@@ -0,0 +1,67 @@
+//! Encrypted key files, keeping the key out of logs and probing the endpoint with it.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_rpg::{
+    credentials::{self, Credentials, KeyStatus},
+    mock_server::{self, MockConfig},
+    DialogBackendPlugin, DialogSettings,
+};
+
+#[test]
+fn encrypted_keys_need_the_right_passphrase() {
+    let contents = credentials::encrypt_key("sk-secret\n", "hunter2");
+    assert!(!contents.contains("sk-secret"));
+    assert_eq!(
+        credentials::decrypt_key(&contents, "hunter2").unwrap(),
+        "sk-secret"
+    );
+    assert!(credentials::decrypt_key(&contents, "hunter3").is_err());
+}
+
+#[test]
+fn keys_stay_out_of_debug_output_and_logs() {
+    let credentials = Credentials::from_key("sk-secret");
+    assert!(!format!("{:?}", credentials).contains("sk-secret"));
+    assert_eq!(
+        credentials.redact("Bearer sk-secret was refused"),
+        "Bearer [redacted] was refused"
+    );
+}
+
+/// The key's status once the startup probe against a mock server expecting `server_key` is done.
+fn probe(server_key: &str, key: &str) -> KeyStatus {
+    let endpoint = mock_server::spawn(MockConfig {
+        api_key: Some(server_key.to_string()),
+        ..default()
+    })
+    .unwrap();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Credentials::from_key(key))
+        .insert_resource(DialogSettings {
+            endpoint,
+            ..default()
+        })
+        .add_plugins(DialogBackendPlugin);
+    let started = Instant::now();
+    loop {
+        app.update();
+        let status = app.world.resource::<Credentials>().status.clone();
+        if !matches!(status, KeyStatus::Unchecked | KeyStatus::Checking) {
+            return status;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "no answer");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn the_probe_accepts_good_keys_and_rejects_bad_ones() {
+    assert_eq!(probe("sk-good", "sk-good"), KeyStatus::Valid);
+    assert!(matches!(probe("sk-good", "sk-bad"), KeyStatus::Rejected(_)));
+}
@@ -0,0 +1,510 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_rpg::{
+    clock::{GameTime, TimeSkipped, MINUTES_PER_DAY},
+    death::{CharacterDied, Dead},
+    dialog::{ModelRole, OpenAIMessage},
+    navigation::{NavPath, NavigationGrid, Step},
+    perception::Perception,
+    plan::PlanStep,
+    Action, Character, FarmingSettings, Item, Memory, NPCState, Plant, Region, NPC,
+};
+use common::{run_for, spawn_character, spawn_npc, test_app, tool_call, ScriptedDialog};
+
+fn spawn_region(app: &mut App, name: &str, range: Rect) {
+    app.world.spawn(Region {
+        name: name.to_string(),
+        range,
+    });
+}
+
+fn spawn_plant(app: &mut App, growth: f32, position: Vec2) -> Entity {
+    app.world
+        .spawn((
+            Plant { growth },
+            Transform::from_translation(position.extend(0.0)),
+            Handle::<Image>::default(),
+        ))
+        .id()
+}
+
+fn state(app: &App, npc: Entity) -> NPCState {
+    app.world.get::<NPC>(npc).unwrap().state.clone()
+}
+
+fn plants_carried(app: &App, entity: Entity) -> u32 {
+    app.world
+        .get::<Character>(entity)
+        .unwrap()
+        .items
+        .iter()
+        .filter(|(item, _)| *item == Item::Plant)
+        .map(|(_, count)| count)
+        .sum()
+}
+
+#[test]
+fn farmers_harvest_grown_plants_in_their_region() {
+    let mut app = test_app();
+    spawn_region(&mut app, "Field", Rect::new(-200.0, -200.0, 200.0, 200.0));
+    let ripe = spawn_plant(&mut app, 1.0, Vec2::new(120.0, 0.0));
+    let seedling = spawn_plant(&mut app, 0.1, Vec2::new(-60.0, 0.0));
+    let outside = spawn_plant(&mut app, 1.0, Vec2::new(0.0, 300.0));
+    let farmer = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Farming,
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 3.0);
+
+    assert_eq!(plants_carried(&app, farmer), 1);
+    assert!(!app.world.get::<Plant>(ripe).unwrap().is_grown());
+    assert!(app.world.get::<Plant>(seedling).unwrap().growth < 1.0);
+    assert!(app.world.get::<Plant>(outside).unwrap().is_grown());
+}
+
+#[test]
+fn travelers_arrive_and_go_idle() {
+    let mut app = test_app();
+    let market = Rect::new(400.0, 400.0, 600.0, 600.0);
+    spawn_region(&mut app, "Market", market);
+    let traveler = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Traveling("Market".to_string()),
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 10.0);
+
+    let position = app.world.get::<Transform>(traveler).unwrap().translation;
+    assert!(market.contains(position.xy()), "stopped at {}", position);
+    assert!(matches!(state(&app, traveler), NPCState::Idle));
+}
+
+#[test]
+fn travelers_to_unknown_places_go_idle() {
+    let mut app = test_app();
+    let traveler = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            state: NPCState::Traveling("Atlantis".to_string()),
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 0.5);
+
+    assert!(matches!(state(&app, traveler), NPCState::Idle));
+    assert_eq!(
+        app.world.get::<Transform>(traveler).unwrap().translation,
+        Vec3::ZERO
+    );
+}
+
+#[test]
+fn set_task_tool_calls_change_state() {
+    let mut app = test_app();
+    app.world
+        .resource_mut::<ScriptedDialog>()
+        .replies
+        .push_back(tool_call("set_task", r#"{"task": "farming"}"#));
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            chat_cooldown: 0.0,
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 0.5);
+
+    assert_eq!(app.world.resource::<ScriptedDialog>().prompted, ["Ann"]);
+    assert!(matches!(state(&app, npc), NPCState::Farming));
+}
+
+#[test]
+fn starving_characters_eat() {
+    let mut app = test_app();
+    let character = spawn_character(
+        &mut app,
+        Character {
+            name: "Ann".to_string(),
+            saturation: 10.0,
+            items: vec![(Item::Plant, 3)],
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 0.5);
+
+    let saturation = app.world.get::<Character>(character).unwrap().saturation;
+    assert!(saturation > 25.0, "saturation is {}", saturation);
+    assert!(plants_carried(&app, character) < 3);
+}
+
+#[test]
+fn invalid_tool_arguments_dont_crash() {
+    let mut app = test_app();
+    let mut script = app.world.resource_mut::<ScriptedDialog>();
+    script.replies.extend([
+        tool_call("set_task", "not json"),
+        tool_call("set_task", r#"{"task": 5}"#),
+        tool_call("set_task", r#"{"task": "traveling"}"#),
+        tool_call("set_task", r#"{"task": "dancing"}"#),
+        tool_call("fly_away", "{}"),
+    ]);
+    let npcs = ["Ann", "Ben", "Cat", "Dan", "Eve"].map(|name| {
+        spawn_npc(
+            &mut app,
+            name,
+            NPC {
+                state: NPCState::Farming,
+                chat_cooldown: 0.0,
+                ..default()
+            },
+            Vec2::ZERO,
+        )
+    });
+
+    run_for(&mut app, 0.5);
+
+    assert_eq!(app.world.resource::<ScriptedDialog>().prompted.len(), 5);
+    let states = npcs.map(|npc| state(&app, npc));
+    // unparseable calls and unknown tools leave the task alone, unusable tasks fall back to idle
+    let farming = states
+        .iter()
+        .filter(|state| matches!(state, NPCState::Farming))
+        .count();
+    let idle = states
+        .iter()
+        .filter(|state| matches!(state, NPCState::Idle))
+        .count();
+    assert_eq!((farming, idle), (3, 2));
+}
+
+#[test]
+fn idle_npcs_plan_and_long_memories_get_summarized() {
+    let mut app = test_app();
+    let memories = (0..45)
+        .map(|minute| Memory {
+            time: GameTime::default(),
+            actor: "Ann".to_string(),
+            action: Action::Emote(format!("counts to {}", minute)),
+        })
+        .collect();
+    app.world.resource_mut::<ScriptedDialog>().replies.extend([
+        OpenAIMessage {
+            role: "assistant".to_string(),
+            content: Some("You spent the morning counting.".to_string()),
+            name: None,
+            tool_calls: None,
+        },
+        tool_call("set_task", r#"{"task": "farming"}"#),
+    ]);
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            chat_cooldown: 0.0,
+            history: memories,
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 0.5);
+
+    let script = app.world.resource::<ScriptedDialog>();
+    assert_eq!(
+        &script.roles[..2],
+        &[ModelRole::Summary, ModelRole::Planning]
+    );
+    let history = &app.world.get::<NPC>(npc).unwrap().history;
+    // the summary and the twelve most recent memories
+    assert_eq!(history.len(), 13);
+    assert!(
+        history[0].action
+            == Action::Event("You remember: You spent the morning counting.".to_string())
+    );
+    assert!(matches!(state(&app, npc), NPCState::Farming));
+}
+
+#[test]
+fn plans_run_step_by_step() {
+    let mut app = test_app();
+    let market = Rect::new(400.0, 400.0, 600.0, 600.0);
+    spawn_region(&mut app, "Market", market);
+    app.world
+        .resource_mut::<ScriptedDialog>()
+        .replies
+        .push_back(tool_call(
+            "set_plan",
+            r#"{"goal": "sell turnips", "steps": [
+            {"action": "travel", "destination": "Market"},
+            {"action": "farm", "minutes": 30},
+            {"action": "return_home"}
+        ]}"#,
+        ));
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            chat_cooldown: 0.0,
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 0.5);
+    assert_eq!(state(&app, npc), NPCState::Traveling("Market".to_string()));
+
+    run_for(&mut app, 10.0);
+    let plan = app.world.get::<NPC>(npc).unwrap().plan.clone().unwrap();
+    assert_eq!(plan.current, 1);
+    assert_eq!(state(&app, npc), NPCState::Farming);
+
+    // without a home to return to, the last step is done straight away
+    run_for(&mut app, 25.0);
+    let npc = app.world.get::<NPC>(npc).unwrap();
+    assert!(npc.plan.is_none());
+    assert!(
+        npc.history.last().unwrap().action
+            == Action::Event("You finished your plan to sell turnips.".to_string())
+    );
+}
+
+#[test]
+fn failed_plans_make_npcs_plan_again() {
+    let mut app = test_app();
+    app.world
+        .resource_mut::<ScriptedDialog>()
+        .replies
+        .push_back(tool_call(
+            "set_plan",
+            r#"{"goal": "find gold", "steps": [{"action": "travel", "destination": "Atlantis"}]}"#,
+        ));
+    let npc = spawn_npc(
+        &mut app,
+        "Ann",
+        NPC {
+            chat_cooldown: 0.0,
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+
+    run_for(&mut app, 0.5);
+
+    assert_eq!(
+        app.world.resource::<ScriptedDialog>().roles,
+        [ModelRole::Planning, ModelRole::Planning]
+    );
+    let npc = app.world.get::<NPC>(npc).unwrap();
+    assert!(npc.plan.is_none());
+    assert!(npc.history.iter().any(|memory| memory.action
+        == Action::Event(
+            "Your plan to find gold failed because you could not get to Atlantis.".to_string()
+        )));
+}
+
+#[test]
+fn food_traded_for_work_is_worked_off() {
+    let mut app = test_app();
+    let bill = spawn_npc(
+        &mut app,
+        "Bill",
+        NPC {
+            home: "Bill's Farm".to_string(),
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+    app.world.get_mut::<Character>(bill).unwrap().items = vec![(Item::Plant, 3)];
+    let theo = spawn_npc(&mut app, "Theo", NPC::default(), Vec2::new(50.0, 0.0));
+    app.world
+        .get_mut::<Character>(theo)
+        .unwrap()
+        .actions
+        .push(Action::Talk(
+            "Bill, I'll work your fields for an hour in return for some food.".to_string(),
+        ));
+
+    app.update();
+
+    assert_eq!(plants_carried(&app, theo), 1);
+    assert_eq!(plants_carried(&app, bill), 2);
+    let plan = app.world.get::<NPC>(theo).unwrap().plan.clone().unwrap();
+    assert_eq!(plan.goal, "work off the food Bill traded you");
+    assert_eq!(
+        plan.steps[0],
+        PlanStep::Travel {
+            destination: "Bill's Farm".to_string()
+        }
+    );
+}
+
+#[test]
+fn people_behind_an_npc_are_heard_but_not_seen() {
+    let mut app = test_app();
+    // NPCs start out facing down
+    let npc = spawn_npc(&mut app, "Ann", NPC::default(), Vec2::ZERO);
+    spawn_npc(&mut app, "Bob", NPC::default(), Vec2::new(0.0, 200.0));
+
+    app.update();
+
+    let perception = app.world.get::<Perception>(npc).unwrap();
+    assert!(perception.visible.is_empty());
+    assert_eq!(perception.audible, vec!["Bob".to_string()]);
+}
+
+#[test]
+fn walkers_blocked_by_someone_give_up() {
+    let mut grid = NavigationGrid::new(Rect::new(-1000.0, -1000.0, 1000.0, 1000.0), vec![]);
+    let mut path = NavPath::default();
+    let mut position = Vec3::ZERO;
+    let target = Vec2::new(200.0, 0.0);
+    // someone stands right where the walker is heading
+    let others = [target];
+
+    let mut steps = 0;
+    let outcome = loop {
+        match path.step_towards(&mut grid, &mut position, target, 2.5, &others) {
+            Step::Moving => steps += 1,
+            outcome => break outcome,
+        }
+        assert!(steps < 1000, "still walking at {}", position);
+    };
+    assert!(matches!(outcome, Step::Unreachable));
+    // the next step starts afresh in case the way has cleared
+    assert!(matches!(
+        path.step_towards(&mut grid, &mut position, target, 2.5, &others),
+        Step::Moving
+    ));
+    assert!(
+        position.xy().distance(target) < 50.0,
+        "stopped at {}",
+        position
+    );
+}
+
+#[test]
+fn harvesting_picks_the_chosen_plant() {
+    let mut app = test_app();
+    // characters start out facing down
+    let in_front = spawn_plant(&mut app, 1.0, Vec2::new(0.0, -20.0));
+    let chosen = spawn_plant(&mut app, 1.0, Vec2::new(30.0, -30.0));
+    let player = spawn_character(&mut app, Character::default(), Vec2::ZERO);
+    app.world
+        .get_mut::<Character>(player)
+        .unwrap()
+        .actions
+        .push(Action::Harvest(Some(chosen)));
+
+    app.update();
+
+    assert_eq!(plants_carried(&app, player), 1);
+    assert!(!app.world.get::<Plant>(chosen).unwrap().is_grown());
+    assert!(app.world.get::<Plant>(in_front).unwrap().is_grown());
+}
+
+#[test]
+fn crops_grow_through_skipped_time() {
+    let mut app = test_app();
+    let plant = spawn_plant(&mut app, 0.0, Vec2::ZERO);
+    app.world.send_event(TimeSkipped {
+        minutes: MINUTES_PER_DAY,
+    });
+
+    app.update();
+
+    let growth = app.world.get::<Plant>(plant).unwrap().growth;
+    let per_day = app.world.resource::<FarmingSettings>().growth_per_day;
+    assert!(growth >= per_day, "grew to {}", growth);
+}
+
+#[test]
+fn food_is_only_given_to_the_living() {
+    let mut app = test_app();
+    let giver = spawn_character(
+        &mut app,
+        Character {
+            name: "Ann".to_string(),
+            items: vec![(Item::Plant, 3)],
+            ..default()
+        },
+        Vec2::ZERO,
+    );
+    let ghost = spawn_character(
+        &mut app,
+        Character {
+            name: "Bob".to_string(),
+            ..default()
+        },
+        Vec2::new(50.0, 0.0),
+    );
+    app.world.entity_mut(ghost).insert(Dead {
+        cause: "starvation".to_string(),
+        time_of_death: 0.0,
+    });
+    app.world.get_mut::<Character>(giver).unwrap().actions = vec![
+        Action::Give("Bob".to_string()),
+        Action::Give("Nobody".to_string()),
+    ];
+
+    app.update();
+
+    assert_eq!(plants_carried(&app, giver), 3);
+    assert_eq!(plants_carried(&app, ghost), 0);
+}
+
+#[test]
+fn deaths_are_witnessed_by_those_who_see_or_hear_them() {
+    let mut app = test_app();
+    // NPCs start out facing down, so Ann sees Bob and Cat only hears him
+    let ann = spawn_npc(&mut app, "Ann", NPC::default(), Vec2::ZERO);
+    spawn_character(
+        &mut app,
+        Character {
+            name: "Bob".to_string(),
+            ..default()
+        },
+        Vec2::new(0.0, -100.0),
+    );
+    let cat = spawn_npc(&mut app, "Cat", NPC::default(), Vec2::new(0.0, -400.0));
+    let dan = spawn_npc(&mut app, "Dan", NPC::default(), Vec2::new(0.0, -900.0));
+    app.update();
+
+    app.world.send_event(CharacterDied {
+        name: "Bob".to_string(),
+        position: Vec2::new(0.0, -100.0),
+        cause: "a fall".to_string(),
+    });
+    app.update();
+
+    let remembers = |npc: Entity, event: &str| {
+        app.world
+            .get::<NPC>(npc)
+            .unwrap()
+            .history
+            .iter()
+            .any(|memory| memory.action == Action::Event(event.to_string()))
+    };
+    assert!(remembers(ann, "You saw Bob die of a fall."));
+    assert!(remembers(cat, "You heard Bob die of a fall nearby."));
+    assert!(app.world.get::<NPC>(dan).unwrap().history.is_empty());
+}
@@ -0,0 +1,193 @@
+//! The mock server's replies, and the dialog backend talking to it over HTTP.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_rpg::{
+    credentials::Credentials,
+    dialog::{
+        DialogPrompt, DialogReply, ModelRole, OpenAIErrorResponse, OpenAIMessage, OpenAIRequest,
+    },
+    mock_server::{self, MockConfig, MockMode, MockReply, MockResponder, MockRule, MockScript},
+    DialogBackendPlugin, DialogSettings,
+};
+
+fn user(content: &str) -> OpenAIMessage {
+    OpenAIMessage {
+        role: "user".to_string(),
+        content: Some(content.to_string()),
+        name: None,
+        tool_calls: None,
+    }
+}
+
+fn request(content: &str) -> OpenAIRequest {
+    OpenAIRequest {
+        messages: vec![user(content)],
+        model: "gpt-3.5-turbo".to_string(),
+        logit_bias: None,
+        temperature: 1.0,
+        max_tokens: 64,
+        top_p: 1.0,
+        frequency_penalty: 0.0,
+        presence_penalty: 0.0,
+        stop: vec![],
+        tools: vec![],
+        response_format: None,
+    }
+}
+
+fn say(speech: &str) -> MockReply {
+    MockReply::Say(speech.to_string())
+}
+
+#[test]
+fn scripted_replies_repeat_in_order() {
+    let mut responder = MockResponder::new(MockConfig {
+        script: MockScript {
+            replies: vec![say("one"), say("two")],
+            rules: vec![],
+        },
+        ..default()
+    });
+    let replies: Vec<_> = (0..3)
+        .map(|_| responder.reply_to(&request("Hello")))
+        .collect();
+    assert_eq!(replies, vec![say("one"), say("two"), say("one")]);
+}
+
+#[test]
+fn rules_match_the_prompt_ignoring_case() {
+    let mut responder = MockResponder::new(MockConfig {
+        mode: MockMode::Rules,
+        script: MockScript {
+            replies: vec![say("fallback")],
+            rules: vec![MockRule {
+                contains: vec!["you are jeff".to_string(), "starving".to_string()],
+                reply: say("I need food."),
+            }],
+        },
+        ..default()
+    });
+    assert_eq!(
+        responder.reply_to(&request("You are Jeff. You are starving.")),
+        say("I need food.")
+    );
+    assert_eq!(
+        responder.reply_to(&request("You are Jeff.")),
+        say("fallback")
+    );
+}
+
+#[test]
+fn errors_use_the_openai_error_format() {
+    let mut responder = MockResponder::new(MockConfig::default());
+    let reply = MockReply::Error {
+        status: 429,
+        message: "Slow down".to_string(),
+        error_type: "rate_limit_exceeded".to_string(),
+    };
+    let (status, body) = responder.respond(&request("Hello"), reply).unwrap();
+    assert_eq!(status, 429);
+    let error: OpenAIErrorResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(error.error.error_type, "rate_limit_exceeded");
+}
+
+#[derive(Resource, Default)]
+struct Replies(Vec<Option<OpenAIMessage>>);
+
+fn collect_replies(mut replies: EventReader<DialogReply>, mut collected: ResMut<Replies>) {
+    collected
+        .0
+        .extend(replies.read().map(|reply| reply.message.clone()));
+}
+
+/// Sends one prompt through the dialog backend to a mock server answering with `reply`, giving
+/// up on the request after `request_timeout` seconds.
+fn ask_mock(reply: MockReply, request_timeout: f32) -> Option<OpenAIMessage> {
+    let endpoint = mock_server::spawn(MockConfig {
+        script: MockScript {
+            replies: vec![reply],
+            rules: vec![],
+        },
+        ..default()
+    })
+    .unwrap();
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Credentials::from_key("test"))
+        .insert_resource(DialogSettings {
+            endpoint,
+            request_timeout,
+            ..default()
+        })
+        .add_plugins(DialogBackendPlugin)
+        .init_resource::<Replies>()
+        .add_systems(Update, collect_replies);
+    let npc = app.world.spawn_empty().id();
+    app.world.send_event(DialogPrompt {
+        npc,
+        messages: vec![user("Hello")],
+        role: ModelRole::Conversation,
+        tasks: vec![],
+        model: None,
+        temperature: None,
+        variant: None,
+        request: 0,
+    });
+
+    let started = Instant::now();
+    while app.world.resource::<Replies>().0.is_empty() {
+        assert!(started.elapsed() < Duration::from_secs(10), "no reply");
+        app.update();
+        thread::sleep(Duration::from_millis(10));
+    }
+    app.world.resource_mut::<Replies>().0.remove(0)
+}
+
+#[test]
+fn backend_receives_tool_calls_over_http() {
+    let message = ask_mock(
+        MockReply::ToolCall {
+            name: "set_task".to_string(),
+            arguments: serde_json::json!({"task": "farming"}),
+        },
+        5.0,
+    )
+    .expect("a message");
+    let call = &message.tool_calls.expect("a tool call")[0];
+    assert_eq!(call.function.name, "set_task");
+    assert_eq!(call.function.arguments, r#"{"task":"farming"}"#);
+}
+
+#[test]
+fn backend_survives_error_responses() {
+    let message = ask_mock(
+        MockReply::Error {
+            status: 500,
+            message: "Oops".to_string(),
+            error_type: "server_error".to_string(),
+        },
+        5.0,
+    );
+    assert!(message.is_none());
+}
+
+#[test]
+fn backend_survives_malformed_responses() {
+    let page = MockReply::Malformed("<html>502 Bad Gateway</html>".to_string());
+    assert!(ask_mock(page, 5.0).is_none());
+    let no_choices = MockReply::Malformed(r#"{"choices": []}"#.to_string());
+    assert!(ask_mock(no_choices, 5.0).is_none());
+}
+
+#[test]
+fn hung_requests_fail_after_the_timeout() {
+    let started = Instant::now();
+    assert!(ask_mock(MockReply::Timeout, 0.5).is_none());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
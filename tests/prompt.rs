@@ -0,0 +1,31 @@
+//! Rendering the bundled prompt templates.
+
+use bevy_rpg::{
+    dialog::ModelRole,
+    prompt::{PromptContext, PromptTemplates, DEFAULT_VARIANT, PROMPT_DIRECTORY},
+};
+
+#[test]
+fn functions_are_only_mentioned_when_offered() {
+    let templates = PromptTemplates::load(PROMPT_DIRECTORY);
+    for variant in [DEFAULT_VARIANT, "no_inventory"] {
+        for role in [
+            ModelRole::Bark,
+            ModelRole::Conversation,
+            ModelRole::Planning,
+        ] {
+            let context = PromptContext {
+                name: "Theo".to_string(),
+                role: role.name(),
+                tools: role.uses_tools(),
+                ..Default::default()
+            };
+            let system = templates.render(variant, &context).unwrap().system;
+            assert_eq!(
+                system.contains("call a function"),
+                role.uses_tools(),
+                "{variant} {role:?}"
+            );
+        }
+    }
+}
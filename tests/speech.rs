@@ -0,0 +1,64 @@
+//! Recovering what an NPC said and did from the ways models format their replies.
+
+use bevy_rpg::speech::parse_npc_response;
+
+fn parse(content: &str) -> bevy_rpg::speech::NpcResponse {
+    let characters = ["Theo", "Bill", "Player"].map(String::from);
+    parse_npc_response(content, "Theo", &characters)
+}
+
+#[test]
+fn speaker_tags_are_stripped() {
+    assert_eq!(parse("Theo:\"Hi\"").speech.as_deref(), Some("Hi"));
+    assert_eq!(parse("**Theo**: Hi").speech.as_deref(), Some("Hi"));
+    assert_eq!(parse("Hi there").speech.as_deref(), Some("Hi there"));
+}
+
+#[test]
+fn stage_directions_become_emotes() {
+    let response = parse("*sighs*");
+    assert_eq!(response.speech, None);
+    assert_eq!(response.emotes, vec!["sighs".to_string()]);
+
+    let response = parse("Theo: *waves* Good morning.");
+    assert_eq!(response.speech.as_deref(), Some("Good morning."));
+    assert_eq!(response.emotes, vec!["waves".to_string()]);
+}
+
+#[test]
+fn multi_line_replies_are_joined() {
+    let response = parse("Theo: Morning.\n\n*stretches*\nTheo: Lovely day.");
+    assert_eq!(response.speech.as_deref(), Some("Morning. Lovely day."));
+    assert_eq!(response.emotes, vec!["stretches".to_string()]);
+}
+
+#[test]
+fn other_characters_lines_are_dropped() {
+    let response = parse("Theo: Hello Bill.\nBill: Hello Theo.\nTheo: How are you?");
+    assert_eq!(response.speech.as_deref(), Some("Hello Bill."));
+    assert!(parse("Player: What now?").is_empty());
+}
+
+#[test]
+fn colons_in_speech_are_kept() {
+    assert_eq!(
+        parse("Listen: the harvest is late").speech.as_deref(),
+        Some("Listen: the harvest is late")
+    );
+    assert_eq!(
+        parse("Theo: Look: rain").speech.as_deref(),
+        Some("Look: rain")
+    );
+}
+
+#[test]
+fn json_replies_are_read() {
+    let response = parse(
+        "```json\n{\"speech\": \"Theo: Off to work.\", \"emote\": \"yawns.\", \"task\": \"traveling\", \"destination\": \"Bill's Farm\"}\n```",
+    );
+    assert_eq!(response.speech.as_deref(), Some("Off to work."));
+    assert_eq!(response.emotes, vec!["yawns".to_string()]);
+    let task = response.task_arguments.unwrap();
+    assert_eq!(task["task"], "traveling");
+    assert_eq!(task["destination"], "Bill's Farm");
+}
@@ -0,0 +1,240 @@
+//! The think scheduler's request limit, priorities, cancellations, timeouts and stale replies.
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use bevy_rpg::{
+    death::Dead,
+    dialog::{DialogCancel, DialogPrompt, DialogReply, ModelRole},
+    npc::NPCState,
+    think::{self, Staleness, ThinkPriority, ThinkScheduler, ThoughtContext},
+    NpcAiSettings, NPC,
+};
+
+/// Prompts the scheduler sent, in order, with their request numbers.
+#[derive(Resource, Default)]
+struct Sent(Vec<(Entity, u64)>);
+
+impl Sent {
+    fn npcs(&self) -> Vec<Entity> {
+        self.0.iter().map(|&(npc, _)| npc).collect()
+    }
+}
+
+#[derive(Resource, Default)]
+struct Cancelled(Vec<Entity>);
+
+fn record(
+    mut prompts: EventReader<DialogPrompt>,
+    mut cancels: EventReader<DialogCancel>,
+    mut sent: ResMut<Sent>,
+    mut cancelled: ResMut<Cancelled>,
+) {
+    sent.0
+        .extend(prompts.read().map(|prompt| (prompt.npc, prompt.request)));
+    cancelled.0.extend(cancels.read().map(|cancel| cancel.npc));
+}
+
+/// Just the scheduler, allowing two requests in flight, with nothing answering them.
+fn scheduler_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(NpcAiSettings {
+            max_concurrent_requests: 2,
+            ..default()
+        })
+        .init_resource::<ThinkScheduler>()
+        .init_resource::<Sent>()
+        .init_resource::<Cancelled>()
+        .add_event::<DialogPrompt>()
+        .add_event::<DialogReply>()
+        .add_event::<DialogCancel>()
+        .add_systems(Update, (finish, think::dispatch_thoughts, record).chain());
+    app
+}
+
+fn finish(mut scheduler: ResMut<ThinkScheduler>, mut replies: EventReader<DialogReply>) {
+    for reply in replies.read() {
+        scheduler.finish(reply);
+    }
+}
+
+fn idle(addressees: &[&str]) -> ThoughtContext {
+    ThoughtContext {
+        state: NPCState::Idle,
+        addressees: names(addressees),
+    }
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn prompt(npc: Entity) -> DialogPrompt {
+    DialogPrompt {
+        npc,
+        messages: vec![],
+        role: ModelRole::Conversation,
+        tasks: vec![],
+        model: None,
+        temperature: None,
+        variant: None,
+        request: 0,
+    }
+}
+
+fn request(app: &mut App, npc: Entity, priority: ThinkPriority) {
+    app.world
+        .resource_mut::<ThinkScheduler>()
+        .request(prompt(npc), priority, idle(&[]), 0.0);
+}
+
+/// Answers the NPC's latest prompt.
+fn reply(app: &mut App, npc: Entity) {
+    let request = app
+        .world
+        .resource::<Sent>()
+        .0
+        .iter()
+        .rfind(|(sent, _)| *sent == npc)
+        .unwrap()
+        .1;
+    app.world.send_event(DialogReply {
+        npc,
+        message: None,
+        role: ModelRole::Conversation,
+        variant: None,
+        request,
+    });
+}
+
+#[test]
+fn requests_wait_for_a_free_slot_most_urgent_first() {
+    let mut app = scheduler_app();
+    let npcs: Vec<_> = (0..4)
+        .map(|_| app.world.spawn(NPC::default()).id())
+        .collect();
+    request(&mut app, npcs[0], ThinkPriority::Background);
+    request(&mut app, npcs[1], ThinkPriority::Background);
+    request(&mut app, npcs[2], ThinkPriority::NearPlayer);
+    request(&mut app, npcs[3], ThinkPriority::Addressed);
+    app.update();
+    assert_eq!(app.world.resource::<Sent>().npcs(), vec![npcs[3], npcs[2]]);
+
+    reply(&mut app, npcs[3]);
+    app.update();
+    assert_eq!(app.world.resource::<Sent>().npcs()[2], npcs[0]);
+    let scheduler = app.world.resource::<ThinkScheduler>();
+    assert_eq!((scheduler.in_flight(), scheduler.queued()), (2, 1));
+}
+
+#[test]
+fn one_request_per_npc_unless_addressed() {
+    let mut app = scheduler_app();
+    let npc = app.world.spawn(NPC::default()).id();
+    request(&mut app, npc, ThinkPriority::Background);
+    app.update();
+    request(&mut app, npc, ThinkPriority::NearPlayer);
+    app.update();
+    assert_eq!(app.world.resource::<Sent>().0.len(), 1);
+
+    // the player spoke, so the answer in flight is already out of date
+    request(&mut app, npc, ThinkPriority::Addressed);
+    app.update();
+    assert_eq!(app.world.resource::<Sent>().npcs(), vec![npc, npc]);
+    assert_eq!(app.world.resource::<ThinkScheduler>().in_flight(), 1);
+
+    // the replaced request's answer doesn't free the new request's slot
+    let replaced = app.world.resource::<Sent>().0[0].1;
+    let reply = DialogReply {
+        npc,
+        message: None,
+        role: ModelRole::Conversation,
+        variant: None,
+        request: replaced,
+    };
+    assert!(app
+        .world
+        .resource_mut::<ThinkScheduler>()
+        .finish(&reply)
+        .is_none());
+    assert_eq!(app.world.resource::<ThinkScheduler>().in_flight(), 1);
+}
+
+#[test]
+fn sleeping_and_dead_npcs_stop_thinking() {
+    let mut app = scheduler_app();
+    let sleeper = app.world.spawn(NPC::default()).id();
+    let dying = app.world.spawn(NPC::default()).id();
+    request(&mut app, sleeper, ThinkPriority::Background);
+    request(&mut app, dying, ThinkPriority::Background);
+    app.update();
+
+    app.world.get_mut::<NPC>(sleeper).unwrap().state = NPCState::Sleeping;
+    app.world.entity_mut(dying).insert(Dead {
+        cause: "hunger".to_string(),
+        time_of_death: 0.0,
+    });
+    app.update();
+    let mut cancelled = app.world.resource::<Cancelled>().0.clone();
+    cancelled.sort();
+    let mut expected = vec![sleeper, dying];
+    expected.sort();
+    assert_eq!(cancelled, expected);
+    assert_eq!(app.world.resource::<ThinkScheduler>().in_flight(), 0);
+}
+
+#[test]
+fn hung_requests_give_up_their_slot() {
+    let mut app = scheduler_app();
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+        100,
+    )))
+    .insert_resource(NpcAiSettings {
+        max_concurrent_requests: 2,
+        stale_after: 1.0,
+        ..default()
+    });
+    let npcs: Vec<_> = (0..3)
+        .map(|_| app.world.spawn(NPC::default()).id())
+        .collect();
+    request(&mut app, npcs[0], ThinkPriority::Background);
+    request(&mut app, npcs[1], ThinkPriority::Background);
+    app.update();
+    assert_eq!(app.world.resource::<ThinkScheduler>().in_flight(), 2);
+
+    // nothing answers, so both requests time out
+    for _ in 0..15 {
+        app.update();
+    }
+    let mut cancelled = app.world.resource::<Cancelled>().0.clone();
+    cancelled.sort();
+    assert_eq!(cancelled, vec![npcs[0], npcs[1]]);
+    assert_eq!(app.world.resource::<ThinkScheduler>().in_flight(), 0);
+
+    let now = app.world.resource::<Time>().elapsed_seconds();
+    app.world.resource_mut::<ThinkScheduler>().request(
+        prompt(npcs[2]),
+        ThinkPriority::Background,
+        idle(&[]),
+        now,
+    );
+    app.update();
+    assert_eq!(app.world.resource::<Sent>().npcs().last(), Some(&npcs[2]));
+}
+
+#[test]
+fn replies_go_stale_when_the_task_changes_or_the_addressees_leave() {
+    let asked = idle(&["James", "Steve"]);
+    let idle_state = NPCState::Idle;
+    assert_eq!(asked.staleness(&idle_state, &names(&["Steve"])), None);
+    assert_eq!(
+        asked.staleness(&idle_state, &names(&["Jacob"])),
+        Some(Staleness::AddresseesLeft)
+    );
+    assert_eq!(
+        asked.staleness(&NPCState::Farming, &names(&["Steve"])),
+        Some(Staleness::TaskChanged)
+    );
+    assert_eq!(idle(&[]).staleness(&idle_state, &[]), None);
+}